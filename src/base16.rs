@@ -0,0 +1,284 @@
+use std::path::Path;
+
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+use crate::document::DocumentFormat;
+use crate::error::{Result, VividError};
+use crate::palette::{parse_color, Rgb};
+use crate::terminal::Background;
+use crate::util::load_yaml_file;
+
+/// The theme that the colors of a scheme are filled into
+const TEMPLATE: &str = include_str!("../config/base16-template.yml");
+
+/// The Base16 slots, followed by the ones that Base24 adds
+const SLOTS: [&str; 24] = [
+    "base00", "base01", "base02", "base03", "base04", "base05", "base06", "base07", "base08",
+    "base09", "base0A", "base0B", "base0C", "base0D", "base0E", "base0F", "base10", "base11",
+    "base12", "base13", "base14", "base15", "base16", "base17",
+];
+
+/// The Base16 slot that each Base24 slot falls back to
+const BASE24_FALLBACKS: [(&str, &str); 8] = [
+    ("base10", "base00"),
+    ("base11", "base00"),
+    ("base12", "base08"),
+    ("base13", "base0A"),
+    ("base14", "base0B"),
+    ("base15", "base0C"),
+    ("base16", "base0D"),
+    ("base17", "base0E"),
+];
+
+/// A Base16 or Base24 color scheme
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scheme {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub variant: Option<String>,
+    /// The colors of all 24 slots, in the order of [`SLOTS`]
+    pub colors: Vec<Rgb>,
+}
+
+impl Scheme {
+    /// Parse a scheme in the classic format (`scheme:`, `base00:` ...) or the
+    /// tinted-theming format (`system:`, `name:`, `palette: {base00: ...}`)
+    pub fn from_string(contents: &str) -> Result<Scheme> {
+        let doc = DocumentFormat::Yaml.parse(contents)?;
+        let palette = match &doc["palette"] {
+            Yaml::Hash(_) => &doc["palette"],
+            _ => &doc,
+        };
+
+        let color = |slot: &str| -> Result<Option<Rgb>> {
+            match &palette[slot] {
+                Yaml::BadValue => Ok(None),
+                value => value
+                    .as_str()
+                    .and_then(parse_color)
+                    .map(Some)
+                    .ok_or_else(|| {
+                        VividError::InvalidBase16Scheme(format!("invalid color {}", slot))
+                    }),
+            }
+        };
+
+        let mut colors = Vec::with_capacity(SLOTS.len());
+        for slot in &SLOTS[..16] {
+            colors.push(
+                color(slot)?.ok_or_else(|| {
+                    VividError::InvalidBase16Scheme(format!("{} is missing", slot))
+                })?,
+            );
+        }
+        for (slot, fallback) in &BASE24_FALLBACKS {
+            let index = SLOTS.iter().position(|s| s == fallback).unwrap();
+            colors.push(color(slot)?.unwrap_or(colors[index]));
+        }
+
+        let string = |key: &str| doc[key].as_str().map(String::from);
+        Ok(Scheme {
+            name: string("scheme").or_else(|| string("name")),
+            author: string("author"),
+            variant: string("variant"),
+            colors,
+        })
+    }
+
+    pub fn from_path(path: &Path) -> Result<Scheme> {
+        let contents = load_yaml_file(path).map_err(|_| {
+            VividError::InvalidBase16Scheme(format!("could not read '{}'", path.display()))
+        })?;
+        Self::from_string(&contents)
+    }
+
+    /// The theme for this scheme: the built-in template with the scheme's
+    /// colors and metadata
+    pub fn to_theme(&self) -> Result<Yaml> {
+        let mut meta = Hash::new();
+        let mut insert = |key: &str, value: &str| {
+            meta.insert(Yaml::String(key.into()), Yaml::String(value.into()));
+        };
+        if let Some(name) = &self.name {
+            insert("name", name);
+        }
+        if let Some(author) = &self.author {
+            insert("author", author);
+        }
+        let variant = match self.variant.as_deref() {
+            Some(variant @ ("light" | "dark")) => variant,
+            _ => Background::from_rgb(self.colors[0]).name(),
+        };
+        insert("variant", variant);
+
+        let colors = SLOTS
+            .iter()
+            .zip(&self.colors)
+            .map(|(slot, (r, g, b))| {
+                (
+                    Yaml::String(slot.to_string()),
+                    Yaml::String(format!("{:02x}{:02x}{:02x}", r, g, b)),
+                )
+            })
+            .collect::<Hash>();
+
+        let mut theme = Hash::new();
+        theme.insert(Yaml::String("meta".into()), Yaml::Hash(meta));
+        theme.insert(Yaml::String("colors".into()), Yaml::Hash(colors));
+        if let Yaml::Hash(template) = DocumentFormat::Yaml.parse(TEMPLATE)? {
+            theme.extend(template);
+        }
+        Ok(Yaml::Hash(theme))
+    }
+}
+
+/// Merge `overrides` into `base`: mappings are merged key by key, all other
+/// values are replaced
+fn merge(base: &mut Yaml, overrides: Yaml) {
+    match (base, overrides) {
+        (Yaml::Hash(base), Yaml::Hash(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Expand a theme with a `base16: path/to/scheme.yaml` key into the theme for
+/// that scheme, with the rest of the theme applied on top. Relative paths are
+/// resolved against `dir`, the directory of the theme.
+pub fn expand(mut doc: Yaml, dir: Option<&Path>) -> Result<Yaml> {
+    let scheme_path = match &mut doc {
+        Yaml::Hash(map) => match map.remove(&Yaml::String("base16".into())) {
+            Some(Yaml::String(path)) => path,
+            Some(_) => return Err(VividError::UnexpectedYamlType),
+            None => return Ok(doc),
+        },
+        _ => return Ok(doc),
+    };
+
+    let path = match dir {
+        Some(dir) => dir.join(&scheme_path),
+        None => Path::new(&scheme_path).to_path_buf(),
+    };
+    let mut theme = Scheme::from_path(&path)?.to_theme()?;
+    merge(&mut theme, doc);
+    Ok(theme)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{expand, Scheme};
+    use crate::color::ColorMode;
+    use crate::document::DocumentFormat;
+    use crate::filetypes::FileTypes;
+    use crate::theme::Theme;
+
+    const CLASSIC: &str = r#"
+scheme: "Default Dark"
+author: "Chris Kempson"
+base00: "181818"
+base01: "282828"
+base02: "383838"
+base03: "585858"
+base04: "b8b8b8"
+base05: "d8d8d8"
+base06: "e8e8e8"
+base07: "f8f8f8"
+base08: "ab4642"
+base09: "dc9656"
+base0A: "f7ca88"
+base0B: "a1b56c"
+base0C: "86c1b9"
+base0D: "7cafc2"
+base0E: "ba8baf"
+base0F: "a16946"
+"#;
+
+    #[test]
+    fn classic_scheme() {
+        let scheme = Scheme::from_string(CLASSIC).unwrap();
+        assert_eq!(Some("Default Dark".into()), scheme.name);
+        assert_eq!(24, scheme.colors.len());
+        // Base24 slots fall back to Base16 ones
+        assert_eq!(scheme.colors[0x0B], scheme.colors[0x14]);
+
+        let theme =
+            Theme::from_document(scheme.to_theme().unwrap(), ColorMode::BitDepth24).unwrap();
+        assert_eq!(
+            Some("dark".into()),
+            theme.meta.variant.map(|v| v.name().to_string())
+        );
+
+        // The template covers every category of the database
+        let filetypes = FileTypes::from_embedded().unwrap();
+        for category in filetypes.mapping.values() {
+            theme.get_style(category).unwrap();
+        }
+        assert_eq!(
+            "4;38;2;171;70;66",
+            theme
+                .get_style(&["archives".into(), "other".into()])
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn tinted_base24_scheme() {
+        let mut scheme = String::from(
+            "system: base24\nname: Example\nvariant: light\npalette:\n  base14: '#00ff00'\n",
+        );
+        for line in CLASSIC.lines().filter(|l| l.starts_with("base")) {
+            scheme.push_str(&format!("  {}\n", line));
+        }
+        let scheme = Scheme::from_string(&scheme).unwrap();
+        assert_eq!(Some("Example".into()), scheme.name);
+        assert_eq!(Some("light".into()), scheme.variant);
+        assert_eq!((0, 255, 0), scheme.colors[0x14]);
+
+        assert!(Scheme::from_string("base00: '000000'").is_err());
+    }
+
+    #[test]
+    fn theme_with_base16_key() {
+        let dir = std::env::temp_dir().join(format!("vivid-base16-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("scheme.yaml"), CLASSIC).unwrap();
+
+        let doc = DocumentFormat::Yaml
+            .parse(
+                "
+base16: scheme.yaml
+colors:
+  pink: ff00ff
+core:
+  directory:
+    foreground: pink
+",
+            )
+            .unwrap();
+        let expanded = expand(doc, Some(&dir));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let theme = Theme::from_document(expanded.unwrap(), ColorMode::BitDepth24).unwrap();
+        assert_eq!(
+            "1;38;2;255;0;255",
+            theme
+                .get_style(&["core".into(), "directory".into()])
+                .unwrap()
+        );
+        assert_eq!(
+            "0;38;2;88;88;88",
+            theme.get_style(&["unimportant".into()]).unwrap()
+        );
+    }
+}
@@ -1,18 +1,40 @@
 use std::str::FromStr;
 
 use crate::error::{Result, VividError};
-use ansi_colours::ansi256_from_rgb;
+use crate::palette::{Palette, Rgb};
+use ansi_colours::{ansi256_from_rgb, rgb_from_ansi256};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ColorMode {
     BitDepth24,
     BitDepth8,
 }
 
+impl ColorMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorMode::BitDepth24 => "24-bit",
+            ColorMode::BitDepth8 => "8-bit",
+        }
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = VividError;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "24-bit" => Ok(ColorMode::BitDepth24),
+            "8-bit" => Ok(ColorMode::BitDepth8),
+            _ => Err(VividError::UnknownColorMode(s.to_string())),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ColorType {
     Foreground,
     Background,
+    Underline,
 }
 
 impl ColorType {
@@ -20,17 +42,20 @@ impl ColorType {
         match self {
             ColorType::Foreground => "38",
             ColorType::Background => "48",
+            ColorType::Underline => "58",
         }
     }
 
     /// Returns `10` if this is `Background`
     ///
     /// This is to be added to a foreground ansi 3-bit code
-    /// to allow it to be a background
-    fn bg_addition(self) -> u8 {
+    /// to allow it to be a background. Underline colors have
+    /// no 3-bit codes, so this returns `None` for them.
+    fn bg_addition(self) -> Option<u8> {
         match self {
-            ColorType::Foreground => 0,
-            ColorType::Background => 10,
+            ColorType::Foreground => Some(0),
+            ColorType::Background => Some(10),
+            ColorType::Underline => None,
         }
     }
 }
@@ -62,6 +87,26 @@ pub enum Ansi3Bit {
     BrightWhite = 97,
 }
 
+/// The names of the ANSI colors, as used in themes (with an `ansi:` prefix)
+pub const ANSI_NAMES: [(&str, Ansi3Bit); 16] = [
+    ("black", Ansi3Bit::Black),
+    ("red", Ansi3Bit::Red),
+    ("green", Ansi3Bit::Green),
+    ("yellow", Ansi3Bit::Yellow),
+    ("blue", Ansi3Bit::Blue),
+    ("magenta", Ansi3Bit::Magenta),
+    ("cyan", Ansi3Bit::Cyan),
+    ("white", Ansi3Bit::White),
+    ("bright_black", Ansi3Bit::BrightBlack),
+    ("bright_red", Ansi3Bit::BrightRed),
+    ("bright_green", Ansi3Bit::BrightGreen),
+    ("bright_yellow", Ansi3Bit::BrightYellow),
+    ("bright_blue", Ansi3Bit::BrightBlue),
+    ("bright_magenta", Ansi3Bit::BrightMagenta),
+    ("bright_cyan", Ansi3Bit::BrightCyan),
+    ("bright_white", Ansi3Bit::BrightWhite),
+];
+
 impl Color {
     pub fn from_hex_str(hex_str: &str) -> Result<Color> {
         let parse_error = || VividError::ColorParseError(hex_str.to_string());
@@ -83,7 +128,21 @@ impl Color {
         }
     }
 
-    pub fn get_style(&self, colortype: ColorType, colormode: ColorMode) -> String {
+    /// The SGR parameters that select this color
+    ///
+    /// There are no 3-bit codes for underline colors, so ANSI palette colors
+    /// are selected by their index in the 256-color palette (`58;5;0` to
+    /// `58;5;15`), which maps to the same 16 colors.
+    ///
+    /// If the actual colors of the terminal's 16-color palette are known, RGB
+    /// colors in 8-bit mode use a palette color if it is closer than any color
+    /// of the 6×6×6 cube and the gray ramp.
+    pub fn get_style(
+        &self,
+        colortype: ColorType,
+        colormode: ColorMode,
+        palette: Option<&Palette>,
+    ) -> String {
         match self {
             Color::Rgb(r, g, b) => match colormode {
                 ColorMode::BitDepth24 => format!(
@@ -96,32 +155,81 @@ impl Color {
                 ColorMode::BitDepth8 => format!(
                     "{ctype};5;{code}",
                     ctype = colortype.get_code(),
-                    code = ansi256_from_rgb((*r, *g, *b))
+                    code = closest_ansi256((*r, *g, *b), palette)
+                ),
+            },
+            Color::Ansi3Bit(color) => match colortype.bg_addition() {
+                Some(addition) => format!("{}", *color as u8 + addition),
+                None => format!(
+                    "{ctype};5;{code}",
+                    ctype = colortype.get_code(),
+                    code = self.ansi256_index()
                 ),
             },
-            Color::Ansi3Bit(color) => format!("{}", *color as u8 + colortype.bg_addition()),
+        }
+    }
+
+    /// The index of this color in the 256-color palette, for programs that
+    /// configure colors by number
+    pub fn ansi256_index(&self) -> u8 {
+        match self {
+            Color::Rgb(r, g, b) => closest_ansi256((*r, *g, *b), None),
+            Color::Ansi3Bit(color) => match *color as u8 {
+                code @ 30..=37 => code - 30,
+                code => code - 90 + 8,
+            },
         }
     }
 
     fn from_ansi_name(s: &str) -> Result<Color> {
-        match s {
-            "ansi:black" => Ok(Self::Ansi3Bit(Ansi3Bit::Black)),
-            "ansi:red" => Ok(Self::Ansi3Bit(Ansi3Bit::Red)),
-            "ansi:green" => Ok(Self::Ansi3Bit(Ansi3Bit::Green)),
-            "ansi:yellow" => Ok(Self::Ansi3Bit(Ansi3Bit::Yellow)),
-            "ansi:blue" => Ok(Self::Ansi3Bit(Ansi3Bit::Blue)),
-            "ansi:magenta" => Ok(Self::Ansi3Bit(Ansi3Bit::Magenta)),
-            "ansi:cyan" => Ok(Self::Ansi3Bit(Ansi3Bit::Cyan)),
-            "ansi:white" => Ok(Self::Ansi3Bit(Ansi3Bit::White)),
-            "ansi:bright_black" => Ok(Self::Ansi3Bit(Ansi3Bit::BrightBlack)),
-            "ansi:bright_red" => Ok(Self::Ansi3Bit(Ansi3Bit::BrightRed)),
-            "ansi:bright_green" => Ok(Self::Ansi3Bit(Ansi3Bit::BrightGreen)),
-            "ansi:bright_yellow" => Ok(Self::Ansi3Bit(Ansi3Bit::BrightYellow)),
-            "ansi:bright_blue" => Ok(Self::Ansi3Bit(Ansi3Bit::BrightBlue)),
-            "ansi:bright_magenta" => Ok(Self::Ansi3Bit(Ansi3Bit::BrightMagenta)),
-            "ansi:bright_cyan" => Ok(Self::Ansi3Bit(Ansi3Bit::BrightCyan)),
-            "ansi:bright_white" => Ok(Self::Ansi3Bit(Ansi3Bit::BrightWhite)),
-            _ => Err(VividError::ColorParseError(s.to_string())),
+        s.strip_prefix("ansi:")
+            .and_then(|name| ANSI_NAMES.iter().find(|(n, _)| *n == name))
+            .map(|(_, color)| Self::Ansi3Bit(*color))
+            .ok_or_else(|| VividError::ColorParseError(s.to_string()))
+    }
+}
+
+/// The 256-color palette index closest to `rgb`. The first 16 colors are only
+/// considered if the terminal told us what they are, as they differ between
+/// terminals.
+fn closest_ansi256(rgb: Rgb, palette: Option<&Palette>) -> u8 {
+    let cube = ansi256_from_rgb(rgb);
+    let mut closest = (distance(rgb, rgb_from_ansi256(cube)), cube);
+    if let Some(palette) = palette {
+        for (index, (name, _)) in ANSI_NAMES.iter().enumerate() {
+            if let Some(color) = palette.colors.get(name) {
+                closest = closest.min((distance(rgb, *color), index as u8));
+            }
+        }
+    }
+    closest.1
+}
+
+/// A weighted Euclidean distance between two colors (the "redmean"
+/// approximation of perceived difference)
+fn distance((r1, g1, b1): Rgb, (r2, g2, b2): Rgb) -> i32 {
+    let mean_red = (i32::from(r1) + i32::from(r2)) / 2;
+    let (dr, dg, db) = (
+        i32::from(r1) - i32::from(r2),
+        i32::from(g1) - i32::from(g2),
+        i32::from(b1) - i32::from(b2),
+    );
+    (((512 + mean_red) * dr * dr) >> 8) + 4 * dg * dg + (((767 - mean_red) * db * db) >> 8)
+}
+
+/// `#rrggbb` for RGB colors, or the ANSI name like `ansi:red`
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Color::Rgb(r, g, b) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+            Color::Ansi3Bit(color) => {
+                let name = ANSI_NAMES
+                    .iter()
+                    .find(|(_, c)| c == color)
+                    .map(|(name, _)| *name)
+                    .unwrap_or_default();
+                write!(f, "ansi:{}", name)
+            }
         }
     }
 }
@@ -136,6 +244,7 @@ impl FromStr for Color {
 #[cfg(test)]
 mod tests {
     use crate::color::Ansi3Bit;
+    use crate::palette::Palette;
 
     use super::{Color, ColorMode, ColorType};
 
@@ -163,33 +272,61 @@ mod tests {
     #[test]
     fn fg_white() {
         let white = Color::Rgb(0xff, 0xff, 0xff);
-        let style_8bit = white.get_style(ColorType::Foreground, ColorMode::BitDepth8);
+        let style_8bit = white.get_style(ColorType::Foreground, ColorMode::BitDepth8, None);
         assert_eq!("38;5;231", style_8bit);
 
-        let style_24bit = white.get_style(ColorType::Foreground, ColorMode::BitDepth24);
+        let style_24bit = white.get_style(ColorType::Foreground, ColorMode::BitDepth24, None);
         assert_eq!("38;2;255;255;255", style_24bit);
     }
 
     #[test]
     fn bg_black() {
         let black = Color::Rgb(0x00, 0x00, 0x00);
-        let style_8bit = black.get_style(ColorType::Background, ColorMode::BitDepth8);
+        let style_8bit = black.get_style(ColorType::Background, ColorMode::BitDepth8, None);
         assert_eq!("48;5;16", style_8bit);
 
-        let style_24bit = black.get_style(ColorType::Background, ColorMode::BitDepth24);
+        let style_24bit = black.get_style(ColorType::Background, ColorMode::BitDepth24, None);
         assert_eq!("48;2;0;0;0", style_24bit);
     }
 
     #[test]
     fn fg_red() {
         let red = Color::Rgb(0xff, 0x00, 0x00);
-        let style_8bit = red.get_style(ColorType::Foreground, ColorMode::BitDepth8);
+        let style_8bit = red.get_style(ColorType::Foreground, ColorMode::BitDepth8, None);
         assert_eq!("38;5;196", style_8bit);
 
-        let style_24bit = red.get_style(ColorType::Foreground, ColorMode::BitDepth24);
+        let style_24bit = red.get_style(ColorType::Foreground, ColorMode::BitDepth24, None);
         assert_eq!("38;2;255;0;0", style_24bit);
     }
 
+    #[test]
+    fn underline_color() {
+        let orange = Color::Rgb(0xff, 0x80, 0x00);
+        let style_8bit = orange.get_style(ColorType::Underline, ColorMode::BitDepth8, None);
+        assert_eq!("58;5;208", style_8bit);
+
+        let style_24bit = orange.get_style(ColorType::Underline, ColorMode::BitDepth24, None);
+        assert_eq!("58;2;255;128;0", style_24bit);
+
+        let red = Color::Ansi3Bit(Ansi3Bit::Red);
+        assert_eq!(
+            "58;5;1",
+            red.get_style(ColorType::Underline, ColorMode::BitDepth24, None)
+        );
+        assert_eq!(
+            "58;5;9",
+            Color::Ansi3Bit(Ansi3Bit::BrightRed).get_style(
+                ColorType::Underline,
+                ColorMode::BitDepth8,
+                None
+            )
+        );
+        assert_eq!(
+            "41",
+            red.get_style(ColorType::Background, ColorMode::BitDepth24, None)
+        );
+    }
+
     #[test]
     fn ansi_3bit() {
         assert_eq!(
@@ -209,4 +346,36 @@ mod tests {
             "ansi:bright_cyan".parse().unwrap()
         );
     }
+
+    #[test]
+    fn display() {
+        assert_eq!("#4ec703", Color::Rgb(0x4e, 0xc7, 0x03).to_string());
+        assert_eq!(
+            "ansi:bright_red",
+            Color::Ansi3Bit(Ansi3Bit::BrightRed).to_string()
+        );
+    }
+
+    #[test]
+    fn ansi256_index() {
+        assert_eq!(196, Color::Rgb(0xff, 0x00, 0x00).ansi256_index());
+        assert_eq!(1, Color::Ansi3Bit(Ansi3Bit::Red).ansi256_index());
+        assert_eq!(14, Color::Ansi3Bit(Ansi3Bit::BrightCyan).ansi256_index());
+    }
+
+    #[test]
+    fn closest_with_terminal_palette() {
+        let red = Color::Rgb(0xcc, 0x24, 0x1d);
+        let style = |palette: &Palette, color_mode| {
+            red.get_style(ColorType::Foreground, color_mode, Some(palette))
+        };
+        let mut palette = Palette::default();
+        assert_eq!("38;5;166", style(&palette, ColorMode::BitDepth8));
+
+        // The terminal's red is a better match than any color of the cube
+        palette.colors.insert("red", (0xcc, 0x24, 0x1d));
+        palette.colors.insert("bright_red", (0xfb, 0x49, 0x34));
+        assert_eq!("38;5;1", style(&palette, ColorMode::BitDepth8));
+        assert_eq!("38;2;204;36;29", style(&palette, ColorMode::BitDepth24));
+    }
 }
@@ -0,0 +1,587 @@
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use yaml_rust::yaml::YamlLoader;
+use yaml_rust::Yaml;
+
+use crate::color::ColorMode;
+use crate::error::{Result, VividError};
+use crate::output::OutputFormat;
+use crate::util::{json_string, load_yaml_file};
+
+/// Either a single theme or a pair of themes for light and dark terminal backgrounds
+#[derive(Debug, Clone, PartialEq)]
+pub enum ThemeChoice {
+    Fixed(String),
+    Auto { light: String, dark: String },
+}
+
+impl Display for ThemeChoice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ThemeChoice::Fixed(theme) => write!(f, "{}", theme),
+            ThemeChoice::Auto { light, dark } => write!(f, "light: {}, dark: {}", light, dark),
+        }
+    }
+}
+
+impl FromStr for ThemeChoice {
+    type Err = VividError;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(ThemeChoice::Fixed(s.to_string()))
+    }
+}
+
+/// A set of optional settings, either at the top level of the config file or
+/// in one of its per-`TERM` sections
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Layer {
+    pub theme: Option<ThemeChoice>,
+    pub color_mode: Option<ColorMode>,
+    pub database: Option<PathBuf>,
+    pub overlay_dirs: Option<Vec<PathBuf>>,
+    pub format: Option<OutputFormat>,
+    pub query_palette: Option<bool>,
+}
+
+/// The contents of `config.yml`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub base: Layer,
+    pub terms: Vec<(String, Layer)>,
+}
+
+impl ConfigFile {
+    pub fn from_path(path: &Path) -> Result<ConfigFile> {
+        let contents = load_yaml_file(path)?;
+        Self::from_string(&contents, path)
+    }
+
+    fn from_string(contents: &str, path: &Path) -> Result<ConfigFile> {
+        let invalid =
+            |message: String| VividError::InvalidConfig(path.display().to_string(), message);
+
+        let docs = YamlLoader::load_from_str(contents)?;
+        let mut config = ConfigFile {
+            path: path.to_owned(),
+            ..Default::default()
+        };
+        let map = match docs.first() {
+            None | Some(Yaml::Null) => return Ok(config),
+            Some(Yaml::Hash(map)) => map,
+            Some(_) => return Err(invalid("expected a mapping".into())),
+        };
+
+        for (key, value) in map {
+            match (key.as_str(), value) {
+                (Some("terms"), Yaml::Hash(terms)) => {
+                    for (term, layer) in terms {
+                        let term = term
+                            .as_str()
+                            .ok_or_else(|| invalid("'terms' keys must be strings".into()))?;
+                        let layer = match layer {
+                            Yaml::Hash(layer) => layer,
+                            _ => {
+                                return Err(invalid(format!("'terms.{}' must be a mapping", term)))
+                            }
+                        };
+                        let mut term_layer = Layer::default();
+                        for (key, value) in layer {
+                            Self::set(&mut term_layer, key, value).map_err(invalid)?;
+                        }
+                        config.terms.push((term.to_string(), term_layer));
+                    }
+                }
+                (Some("terms"), _) => return Err(invalid("'terms' must be a mapping".into())),
+                _ => Self::set(&mut config.base, key, value).map_err(invalid)?,
+            }
+        }
+        Ok(config)
+    }
+
+    fn set(layer: &mut Layer, key: &Yaml, value: &Yaml) -> std::result::Result<(), String> {
+        let key = key.as_str().unwrap_or("");
+        let string = || {
+            value
+                .as_str()
+                .map(|s| s.to_string())
+                .ok_or(format!("'{}' must be a string", key))
+        };
+
+        match key {
+            "theme" => {
+                layer.theme = Some(match value {
+                    Yaml::Hash(_) => match (value["light"].as_str(), value["dark"].as_str()) {
+                        (Some(light), Some(dark)) => ThemeChoice::Auto {
+                            light: light.to_string(),
+                            dark: dark.to_string(),
+                        },
+                        _ => return Err("'theme' needs both a 'light' and a 'dark' theme".into()),
+                    },
+                    _ => ThemeChoice::Fixed(string().map_err(|_| {
+                        "'theme' must be a string or a mapping with 'light' and 'dark'".to_string()
+                    })?),
+                })
+            }
+            "color-mode" => {
+                layer.color_mode = Some(string()?.parse().map_err(|e| format!("{}", e))?)
+            }
+            "database" => layer.database = Some(expand_home(&string()?)),
+            "overlay-dirs" => {
+                let dirs = value
+                    .as_vec()
+                    .ok_or(format!("'{}' must be a list", key))?
+                    .iter()
+                    .map(|dir| dir.as_str().map(expand_home))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or(format!("'{}' must be a list of strings", key))?;
+                layer.overlay_dirs = Some(dirs);
+            }
+            "format" => layer.format = Some(string()?.parse().map_err(|e| format!("{}", e))?),
+            "query-palette" => {
+                layer.query_palette = Some(
+                    value
+                        .as_bool()
+                        .ok_or(format!("'{}' must be true or false", key))?,
+                )
+            }
+            _ => return Err(format!("unknown setting '{}'", key)),
+        }
+        Ok(())
+    }
+
+    /// The per-`TERM` section matching the given terminal, if any
+    ///
+    /// Sections are either matched exactly or, if they end with a `*`, by prefix.
+    pub fn term_layer(&self, term: &str) -> Option<(&str, &Layer)> {
+        self.terms
+            .iter()
+            .find(|(pattern, _)| pattern == term)
+            .or_else(|| {
+                self.terms.iter().find(|(pattern, _)| {
+                    pattern
+                        .strip_suffix('*')
+                        .is_some_and(|prefix| term.starts_with(prefix))
+                })
+            })
+            .map(|(pattern, layer)| (pattern.as_str(), layer))
+    }
+}
+
+/// Where a theme or database was found
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Origin {
+    Overlay,
+    User,
+    System,
+    Embedded,
+}
+
+impl Origin {
+    pub fn name(self) -> &'static str {
+        match self {
+            Origin::Overlay => "overlay",
+            Origin::User => "user",
+            Origin::System => "system",
+            Origin::Embedded => "embedded",
+        }
+    }
+}
+
+/// The directories that are searched for `themes/` and `filetypes.yml`, in
+/// order of precedence: overlay directories first, then the user config directory
+#[derive(Debug, Clone)]
+pub struct ConfigDirs {
+    pub overlays: Vec<PathBuf>,
+    pub user: PathBuf,
+}
+
+impl ConfigDirs {
+    pub fn iter(&self) -> impl Iterator<Item = (Origin, &Path)> {
+        self.overlays
+            .iter()
+            .map(|dir| (Origin::Overlay, dir.as_path()))
+            .chain(std::iter::once((Origin::User, self.user.as_path())))
+    }
+}
+
+/// Expand a leading `~/` to the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), etcetera::home_dir()) {
+        (Some(rest), Ok(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Where the effective value of a setting came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    CommandLine,
+    Environment(&'static str),
+    ConfigFile(PathBuf),
+    TermSection(PathBuf, String),
+    Default,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::CommandLine => write!(f, "command line"),
+            Source::Environment(var) => write!(f, "environment variable {}", var),
+            Source::ConfigFile(path) => write!(f, "config file {}", path.display()),
+            Source::TermSection(path, term) => {
+                write!(f, "config file {}, TERM {}", path.display(), term)
+            }
+            Source::Default => write!(f, "built-in default"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+/// Settings given on the command line
+#[derive(Debug, Default)]
+pub struct CommandLine {
+    pub theme: Option<ThemeChoice>,
+    pub color_mode: Option<ColorMode>,
+    pub database: Option<PathBuf>,
+    pub format: Option<OutputFormat>,
+    pub query_palette: Option<bool>,
+}
+
+/// The effective settings, with precedence command line > environment > config file > built-in
+#[derive(Debug)]
+pub struct Settings {
+    pub theme: Option<Setting<ThemeChoice>>,
+    pub color_mode: Setting<ColorMode>,
+    pub database: Option<Setting<PathBuf>>,
+    pub overlay_dirs: Setting<Vec<PathBuf>>,
+    pub format: Setting<OutputFormat>,
+    /// Whether to ask the terminal for its 16-color palette
+    pub query_palette: Setting<bool>,
+}
+
+fn from_config<T, F>(config: Option<&ConfigFile>, term: Option<&str>, get: F) -> Option<Setting<T>>
+where
+    F: Fn(&Layer) -> Option<T>,
+{
+    let config = config?;
+    if let Some((pattern, layer)) = term.and_then(|term| config.term_layer(term)) {
+        if let Some(value) = get(layer) {
+            return Some(Setting {
+                value,
+                source: Source::TermSection(config.path.clone(), pattern.to_string()),
+            });
+        }
+    }
+    get(&config.base).map(|value| Setting {
+        value,
+        source: Source::ConfigFile(config.path.clone()),
+    })
+}
+
+fn from_env<T, E>(env: &E, var: &'static str) -> Result<Option<Setting<T>>>
+where
+    T: FromStr,
+    E: Fn(&str) -> Option<String>,
+{
+    match env(var) {
+        Some(value) => Ok(Some(Setting {
+            value: value.parse().map_err(|_| {
+                VividError::InvalidEnvironmentVariable(var.to_string(), value.clone())
+            })?,
+            source: Source::Environment(var),
+        })),
+        None => Ok(None),
+    }
+}
+
+fn from_cli<T>(value: Option<T>) -> Option<Setting<T>> {
+    value.map(|value| Setting {
+        value,
+        source: Source::CommandLine,
+    })
+}
+
+fn default<T>(value: T) -> Setting<T> {
+    Setting {
+        value,
+        source: Source::Default,
+    }
+}
+
+impl Settings {
+    pub fn resolve<E>(
+        cli: CommandLine,
+        env: E,
+        config: Option<&ConfigFile>,
+        term: Option<&str>,
+    ) -> Result<Settings>
+    where
+        E: Fn(&str) -> Option<String>,
+    {
+        Ok(Settings {
+            theme: from_cli(cli.theme)
+                .or(from_env(&env, "VIVID_THEME")?)
+                .or_else(|| from_config(config, term, |l| l.theme.clone())),
+            color_mode: from_cli(cli.color_mode)
+                .or(from_env(&env, "VIVID_COLOR_MODE")?)
+                .or_else(|| from_config(config, term, |l| l.color_mode))
+                .unwrap_or_else(|| default(ColorMode::BitDepth24)),
+            database: from_cli(cli.database)
+                .or(from_env(&env, "VIVID_DATABASE")?)
+                .or_else(|| from_config(config, term, |l| l.database.clone())),
+            overlay_dirs: from_config(config, term, |l| l.overlay_dirs.clone())
+                .unwrap_or_else(|| default(vec![])),
+            format: from_cli(cli.format)
+                .or_else(|| from_config(config, term, |l| l.format))
+                .unwrap_or_else(|| default(OutputFormat::LsColors)),
+            query_palette: from_cli(cli.query_palette)
+                .or(from_env(&env, "VIVID_QUERY_PALETTE")?)
+                .or_else(|| from_config(config, term, |l| l.query_palette))
+                .unwrap_or_else(|| default(false)),
+        })
+    }
+}
+
+/// Set a top-level value in the config file, keeping the rest of the file intact.
+/// The value is written as a double-quoted scalar (JSON strings are valid
+/// YAML), so names like `1984`, `yes` or `a: b` stay strings. It replaces the
+/// whole previous value, including the indented lines of a mapping like a
+/// light/dark theme pair.
+pub fn set_value(path: &Path, key: &str, value: &str) -> Result<()> {
+    let contents = if path.exists() {
+        load_yaml_file(path)?
+    } else {
+        String::new()
+    };
+
+    let prefix = format!("{}:", key);
+    let line = format!("{}: {}", key, json_string(value));
+    let mut lines: Vec<String> = contents.lines().map(|l| l.to_string()).collect();
+    match lines.iter().position(|l| l.starts_with(&prefix)) {
+        Some(start) => {
+            // The value ends before the next line that is not indented
+            let mut end = start + 1;
+            for (i, l) in lines.iter().enumerate().skip(start + 1) {
+                if l.trim().is_empty() {
+                    continue;
+                }
+                if !l.starts_with([' ', '\t']) {
+                    break;
+                }
+                end = i + 1;
+            }
+            lines.splice(start..end, [line]);
+        }
+        None => lines.insert(0, line),
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, lines.join("\n") + "\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{set_value, CommandLine, ConfigFile, Settings, Source, ThemeChoice};
+    use crate::color::ColorMode;
+    use crate::output::OutputFormat;
+
+    fn config() -> ConfigFile {
+        ConfigFile::from_string(
+            "
+                theme: molokai
+                color-mode: 24-bit
+                overlay-dirs: [/opt/vivid]
+                format: sh
+                terms:
+                  linux:
+                    color-mode: 8-bit
+                    theme: ansi
+                    query-palette: true
+                  xterm-*:
+                    theme: snazzy
+                  foot:
+                    theme:
+                      light: one-light
+                      dark: one-dark
+            ",
+            Path::new("config.yml"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let config = config();
+        assert_eq!(
+            Some(ThemeChoice::Fixed("molokai".into())),
+            config.base.theme
+        );
+        assert_eq!(
+            Some(vec![PathBuf::from("/opt/vivid")]),
+            config.base.overlay_dirs
+        );
+        assert_eq!(Some(OutputFormat::Sh), config.base.format);
+        assert_eq!(3, config.terms.len());
+        assert_eq!(Some(ColorMode::BitDepth8), config.terms[0].1.color_mode);
+        assert_eq!(
+            Some(ThemeChoice::Auto {
+                light: "one-light".into(),
+                dark: "one-dark".into()
+            }),
+            config.terms[2].1.theme
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse = |s| ConfigFile::from_string(s, Path::new("config.yml"));
+        assert!(parse("").is_ok());
+        assert!(parse("them: molokai").is_err());
+        assert!(parse("color-mode: 16-bit").is_err());
+        assert!(parse("overlay-dirs: /opt").is_err());
+        assert!(parse("terms: [linux]").is_err());
+        assert!(parse("theme: {light: one-light}").is_err());
+        assert!(parse("query-palette: yes").is_err());
+    }
+
+    #[test]
+    fn precedence() {
+        let config = config();
+        let no_env = |_: &str| None;
+
+        let settings =
+            Settings::resolve(CommandLine::default(), no_env, Some(&config), None).unwrap();
+        let theme = settings.theme.unwrap();
+        assert_eq!(ThemeChoice::Fixed("molokai".into()), theme.value);
+        assert_eq!(
+            Source::ConfigFile(PathBuf::from("config.yml")),
+            theme.source
+        );
+        assert_eq!(
+            Source::Default,
+            settings.database.map_or(Source::Default, |d| d.source)
+        );
+
+        let settings =
+            Settings::resolve(CommandLine::default(), no_env, Some(&config), Some("linux"))
+                .unwrap();
+        assert_eq!(
+            ThemeChoice::Fixed("ansi".into()),
+            settings.theme.unwrap().value
+        );
+        assert_eq!(ColorMode::BitDepth8, settings.color_mode.value);
+        assert_eq!(
+            Source::TermSection(PathBuf::from("config.yml"), "linux".into()),
+            settings.color_mode.source
+        );
+        assert_eq!(OutputFormat::Sh, settings.format.value);
+        assert!(settings.query_palette.value);
+
+        let settings = Settings::resolve(
+            CommandLine::default(),
+            no_env,
+            Some(&config),
+            Some("xterm-256color"),
+        )
+        .unwrap();
+        assert_eq!(
+            ThemeChoice::Fixed("snazzy".into()),
+            settings.theme.unwrap().value
+        );
+
+        let env = |var: &str| match var {
+            "VIVID_THEME" => Some("nord".to_string()),
+            "VIVID_COLOR_MODE" => Some("8-bit".to_string()),
+            _ => None,
+        };
+        let settings = Settings::resolve(CommandLine::default(), env, Some(&config), None).unwrap();
+        let theme = settings.theme.unwrap();
+        assert_eq!(ThemeChoice::Fixed("nord".into()), theme.value);
+        assert_eq!(Source::Environment("VIVID_THEME"), theme.source);
+        assert_eq!(ColorMode::BitDepth8, settings.color_mode.value);
+
+        let cli = CommandLine {
+            theme: Some(ThemeChoice::Fixed("ayu".into())),
+            ..Default::default()
+        };
+        let settings = Settings::resolve(cli, env, Some(&config), None).unwrap();
+        let theme = settings.theme.unwrap();
+        assert_eq!(ThemeChoice::Fixed("ayu".into()), theme.value);
+        assert_eq!(Source::CommandLine, theme.source);
+
+        let settings = Settings::resolve(CommandLine::default(), no_env, None, None).unwrap();
+        assert!(settings.theme.is_none());
+        assert_eq!(ColorMode::BitDepth24, settings.color_mode.value);
+        assert_eq!(Source::Default, settings.color_mode.source);
+        assert!(!settings.query_palette.value);
+
+        let env = |var: &str| match var {
+            "VIVID_QUERY_PALETTE" => Some("yes".to_string()),
+            _ => None,
+        };
+        assert!(Settings::resolve(CommandLine::default(), env, None, None).is_err());
+    }
+
+    #[test]
+    fn set_value_keeps_other_lines() {
+        let path = std::env::temp_dir().join(format!("vivid-config-{}.yml", std::process::id()));
+        std::fs::write(
+            &path,
+            "# comment\ntheme: molokai\nterms:\n  linux:\n    theme: ansi\n",
+        )
+        .unwrap();
+
+        set_value(&path, "theme", "snazzy").unwrap();
+        set_value(&path, "color-mode", "8-bit").unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            "color-mode: \"8-bit\"\n# comment\ntheme: \"snazzy\"\nterms:\n  linux:\n    theme: ansi\n",
+            contents
+        );
+    }
+
+    #[test]
+    fn set_value_replaces_mappings() {
+        let path = std::env::temp_dir().join(format!("vivid-pair-{}.yml", std::process::id()));
+        std::fs::write(
+            &path,
+            "theme:\n  light: one-light\n\n  dark: molokai\n\nformat: sh\n",
+        )
+        .unwrap();
+
+        set_value(&path, "theme", "snazzy").unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let config = ConfigFile::from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!("theme: \"snazzy\"\n\nformat: sh\n", contents);
+        assert_eq!(Some(ThemeChoice::Fixed("snazzy".into())), config.base.theme);
+    }
+
+    #[test]
+    fn set_value_round_trip() {
+        let path = std::env::temp_dir().join(format!("vivid-quoted-{}.yml", std::process::id()));
+        for theme in ["1984", "yes", "null", "a: b", "x #y", "it's \"quoted\""] {
+            set_value(&path, "theme", theme).unwrap();
+            let config = ConfigFile::from_path(&path).unwrap();
+            assert_eq!(Some(ThemeChoice::Fixed(theme.into())), config.base.theme);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,632 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use yaml_rust::yaml::Hash;
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::error::{Result, VividError};
+use crate::filetypes::FileTypes;
+use crate::types::{Category, CategoryRef};
+use crate::util::json_string;
+
+/// The two-letter codes that `LS_COLORS` knows, besides file name suffixes
+const CORE_CODES: &[&str] = &[
+    "no", "fi", "rs", "di", "ln", "mh", "pi", "so", "do", "bd", "cd", "or", "mi", "su", "sg", "ca",
+    "tw", "ow", "st", "ex", "lc", "rc", "ec", "cl",
+];
+
+/// A problem with an entry of the filetypes database
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    /// An entry like `cljc` that matches any file name ending in these letters
+    MissingDot(String, Category),
+    /// `:`, `=` or control characters, which would corrupt `LS_COLORS`
+    InvalidCharacter(String, Category),
+    /// Leading or trailing whitespace
+    Whitespace(String, Category),
+    UnknownCoreCode(String, Category),
+    /// A suffix that is overridden by a longer suffix from another category
+    /// for some file names. This is usually intended (`*README.md` overrides
+    /// `*.md`), so it is only a note.
+    Shadowed {
+        entry: String,
+        category: Category,
+        by: String,
+        by_category: Category,
+    },
+}
+
+impl Issue {
+    pub fn is_note(&self) -> bool {
+        matches!(self, Issue::Shadowed { .. })
+    }
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Issue::MissingDot(entry, category) => write!(
+                f,
+                "{}: '{}' looks like an extension, but has no leading dot",
+                category.join("."),
+                entry
+            ),
+            Issue::InvalidCharacter(entry, category) => write!(
+                f,
+                "{}: {:?} contains characters that are not allowed in LS_COLORS",
+                category.join("."),
+                entry
+            ),
+            Issue::Whitespace(entry, category) => write!(
+                f,
+                "{}: {:?} has leading or trailing whitespace",
+                category.join("."),
+                entry
+            ),
+            Issue::UnknownCoreCode(code, category) => {
+                write!(f, "{}: unknown core code '{}'", category.join("."), code)
+            }
+            Issue::Shadowed {
+                entry,
+                category,
+                by,
+                by_category,
+            } => write!(
+                f,
+                "{}: '{}' is overridden by '{}' from {}",
+                category.join("."),
+                entry,
+                by,
+                by_category.join(".")
+            ),
+        }
+    }
+}
+
+/// The entries of the database as they are written in it (`.rs`, `$di`),
+/// sorted
+fn entries(filetypes: &FileTypes) -> Vec<(String, &Category)> {
+    let mut entries = filetypes
+        .mapping
+        .iter()
+        .map(|(key, category)| match key.strip_prefix('*') {
+            Some(suffix) => (suffix.to_string(), category),
+            None => (format!("${}", key), category),
+        })
+        .chain(filetypes.globs.iter().map(|(glob, c)| (glob.clone(), c)))
+        .collect::<Vec<_>>();
+    entries.sort();
+    entries
+}
+
+/// Look for malformed entries in the filetypes database, including notes
+/// about overridden suffixes
+pub fn check(filetypes: &FileTypes) -> Vec<Issue> {
+    let entries = entries(filetypes);
+    let mut issues = check_entries(&entries);
+    issues.extend(check_shadowed(&entries));
+    issues
+}
+
+/// Look for malformed entries, without comparing the entries with each other
+/// like [`check`] does. This is cheap enough to run whenever the database is
+/// loaded.
+pub fn check_malformed(filetypes: &FileTypes) -> Vec<Issue> {
+    check_entries(&entries(filetypes))
+}
+
+fn check_entries(entries: &[(String, &Category)]) -> Vec<Issue> {
+    let mut siblings: HashMap<&Category, Vec<&str>> = HashMap::new();
+    for (entry, category) in entries {
+        siblings.entry(category).or_default().push(entry);
+    }
+
+    let mut issues = Vec::new();
+    for (entry, category) in entries {
+        let issue = |make: fn(String, Category) -> Issue| make(entry.clone(), category.to_vec());
+        if let Some(code) = entry.strip_prefix('$') {
+            if !CORE_CODES.contains(&code) {
+                issues.push(issue(Issue::UnknownCoreCode));
+            }
+        } else if entry.contains([':', '=']) || entry.chars().any(char::is_control) {
+            issues.push(issue(Issue::InvalidCharacter));
+        } else if entry.trim() != entry {
+            issues.push(issue(Issue::Whitespace));
+        } else if looks_like_extension(entry, &siblings[category]) {
+            issues.push(issue(Issue::MissingDot));
+        }
+    }
+    issues
+}
+
+fn check_shadowed(entries: &[(String, &Category)]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    for (entry, category) in entries.iter().filter(|(e, _)| !e.starts_with('$')) {
+        for (by, by_category) in entries {
+            if by.len() > entry.len() && by.ends_with(entry.as_str()) && by_category != category {
+                issues.push(Issue::Shadowed {
+                    entry: entry.clone(),
+                    category: category.to_vec(),
+                    by: by.clone(),
+                    by_category: by_category.to_vec(),
+                });
+            }
+        }
+    }
+    issues
+}
+
+/// Whether a dotless entry looks like an extension that lost its dot: all
+/// other entries of its category are extensions and one of them starts with
+/// the same letters (`cljc` next to `.clj` and `.cljs`)
+fn looks_like_extension(entry: &str, category_entries: &[&str]) -> bool {
+    if entry.contains('.')
+        || !entry
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+    {
+        return false;
+    }
+
+    let siblings = category_entries
+        .iter()
+        .copied()
+        .filter(|e| *e != entry)
+        .collect::<Vec<_>>();
+    let prefix = &entry[..entry.len().min(3)];
+
+    !siblings.is_empty()
+        && siblings.iter().all(|e| e.starts_with('.'))
+        && !siblings.contains(&format!(".{}", entry).as_str())
+        && siblings.iter().any(|e| e[1..].starts_with(prefix))
+}
+
+/// New database entries collected by an importer
+#[derive(Debug, Default, PartialEq)]
+pub struct Import {
+    /// New entries, grouped by category
+    pub entries: Vec<(Category, Vec<String>)>,
+    /// Entries that are already in the database or were claimed by an
+    /// earlier category of the import, with that category
+    pub skipped: Vec<(String, Category)>,
+    /// Patterns that can not be used as a database entry
+    pub unsupported: Vec<String>,
+    claimed: HashMap<String, Category>,
+}
+
+impl Import {
+    /// Add `entry` to `category`, unless it is already known. Entries of the
+    /// database are always reported as skipped, even in the same category.
+    pub fn add(&mut self, filetypes: &FileTypes, category: CategoryRef, entry: &str) {
+        if entry.is_empty()
+            || entry.contains([':', '='])
+            || entry.chars().any(|c| c.is_control() || c.is_whitespace())
+        {
+            self.unsupported.push(entry.to_string());
+            return;
+        }
+        if let Some(existing) = filetypes.category_of(entry) {
+            self.skipped.push((entry.to_string(), existing.clone()));
+            return;
+        }
+        if let Some(existing) = self.claimed.get(entry) {
+            // The same pattern listed twice for one category is not a conflict
+            if existing != category {
+                self.skipped.push((entry.to_string(), existing.clone()));
+            }
+            return;
+        }
+        self.claimed.insert(entry.to_string(), category.to_vec());
+
+        match self.entries.iter_mut().find(|(c, _)| c == category) {
+            Some((_, entries)) => entries.push(entry.to_string()),
+            None => self
+                .entries
+                .push((category.to_vec(), vec![entry.to_string()])),
+        }
+    }
+
+    /// Sort the categories and their entries
+    pub fn finish(mut self) -> Import {
+        for (_, entries) in &mut self.entries {
+            entries.sort_unstable();
+        }
+        self.entries.sort();
+        self
+    }
+
+    /// Add the new entries to a `filetypes.yml` document
+    pub fn apply(&self, doc: &mut Yaml) -> Result<()> {
+        for (category, entries) in &self.entries {
+            add_entries(doc, category, entries)?;
+        }
+        Ok(())
+    }
+
+    /// Add the new entries to the text of a `filetypes.yml`, keeping its
+    /// comments and formatting, see [`insert_entries`]
+    pub fn insert_into(&self, contents: &str) -> Result<String> {
+        let mut contents = contents.to_string();
+        for (category, entries) in &self.entries {
+            contents = insert_entries(&contents, category, entries)?;
+        }
+        Ok(contents)
+    }
+
+    /// Print the changes for review, marking categories that do not exist in
+    /// the database yet
+    pub fn write_changes(&self, out: &mut dyn Write, filetypes: &FileTypes) -> io::Result<()> {
+        for (category, entries) in &self.entries {
+            let is_new = !filetypes.mapping.values().any(|c| c == category);
+            writeln!(
+                out,
+                "{}{}",
+                category.join("."),
+                if is_new { " (new)" } else { "" }
+            )?;
+            for entry in entries {
+                writeln!(out, "  + {}", entry)?;
+            }
+        }
+        for (entry, category) in &self.skipped {
+            writeln!(out, "~ {} (already in {})", entry, category.join("."))?;
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.iter().map(|(_, entries)| entries.len()).sum()
+    }
+}
+
+/// Append `entries` to the list of file types of `category` in a
+/// `filetypes.yml` document, creating the category if necessary
+pub fn add_entries(doc: &mut Yaml, category: CategoryRef, entries: &[String]) -> Result<()> {
+    let invalid = || VividError::InvalidDatabaseCategory(category.join("."));
+
+    if let Yaml::Null | Yaml::BadValue = doc {
+        *doc = Yaml::Hash(Hash::new());
+    }
+
+    let (last, parents) = category.split_last().ok_or_else(invalid)?;
+    let mut node = doc;
+    for key in parents {
+        node = match node {
+            Yaml::Hash(map) => map
+                .entry(Yaml::String(key.clone()))
+                .or_insert_with(|| Yaml::Hash(Hash::new())),
+            _ => return Err(invalid()),
+        };
+    }
+
+    let list = match node {
+        Yaml::Hash(map) => map
+            .entry(Yaml::String(last.clone()))
+            .or_insert_with(|| Yaml::Array(vec![])),
+        _ => return Err(invalid()),
+    };
+    match list {
+        Yaml::Array(list) => {
+            list.extend(entries.iter().cloned().map(Yaml::String));
+            Ok(())
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// The indentation of a line, or `None` for blank lines and comments
+fn indentation(line: &str) -> Option<usize> {
+    let trimmed = line.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        None
+    } else {
+        Some(line.len() - trimmed.len())
+    }
+}
+
+/// The value after `key:` if the line is a mapping entry for `key`
+fn mapping_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let value = line.trim_start().strip_prefix(key)?.strip_prefix(':')?;
+    if !(value.is_empty() || value.starts_with([' ', '\t'])) {
+        return None;
+    }
+    let value = value.trim();
+    Some(if value.starts_with('#') { "" } else { value })
+}
+
+/// An entry as a YAML scalar, quoted if it would not be read back as the
+/// same string (`*.tar.*`, `1984`)
+fn scalar(entry: &str) -> String {
+    let plain = entry
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "._+~".contains(c))
+        && YamlLoader::load_from_str(entry)
+            .ok()
+            .and_then(|docs| docs.into_iter().next())
+            .is_some_and(|doc| doc.as_str() == Some(entry));
+    if plain {
+        entry.to_string()
+    } else {
+        json_string(entry)
+    }
+}
+
+/// Append `entries` to the list of file types of `category` in the text of a
+/// `filetypes.yml`, creating the category if necessary. Unlike
+/// [`add_entries`], this keeps comments and the formatting of the file, but it
+/// only understands block mappings with flow or block lists, like the
+/// embedded database.
+pub fn insert_entries(contents: &str, category: CategoryRef, entries: &[String]) -> Result<String> {
+    let invalid = || VividError::InvalidDatabaseCategory(category.join("."));
+    let values = entries.iter().map(|e| scalar(e)).collect::<Vec<_>>();
+    let mut lines = contents.lines().map(String::from).collect::<Vec<_>>();
+
+    // The lines of the mapping that should contain the next key
+    let (mut start, mut end) = (0, lines.len());
+    let mut parent_indent = None;
+    for (depth, key) in category.iter().enumerate() {
+        let indent = (start..end)
+            .find_map(|i| indentation(&lines[i]))
+            .unwrap_or_else(|| parent_indent.map_or(0, |indent| indent + 2));
+        let found = (start..end).find(|&i| {
+            indentation(&lines[i]) == Some(indent) && mapping_value(&lines[i], key).is_some()
+        });
+
+        let line = match found {
+            Some(line) => line,
+            None => {
+                // Add the missing categories after the last line of the mapping
+                let at = (start..end)
+                    .rev()
+                    .find(|&i| indentation(&lines[i]).is_some())
+                    .map_or(start, |i| i + 1);
+                let mut new = Vec::new();
+                if depth == 0 && at > 0 {
+                    new.push(String::new());
+                }
+                for (level, key) in category[depth..].iter().enumerate() {
+                    let pad = " ".repeat(indent + 2 * level);
+                    if depth + level + 1 == category.len() {
+                        new.push(format!("{}{}: [{}]", pad, key, values.join(", ")));
+                    } else {
+                        new.push(format!("{}{}:", pad, key));
+                    }
+                }
+                lines.splice(at..at, new);
+                return Ok(lines.join("\n") + "\n");
+            }
+        };
+
+        // The block of the key ends at the next line that is not indented
+        // further, apart from list items on the same level
+        let block_end = (line + 1..end)
+            .find(|&i| {
+                indentation(&lines[i]).is_some_and(|i_indent| {
+                    i_indent < indent
+                        || (i_indent == indent && !lines[i].trim_start().starts_with("- "))
+                })
+            })
+            .unwrap_or(end);
+        let value = mapping_value(&lines[line], key).unwrap_or_default();
+
+        if depth + 1 < category.len() {
+            if !value.is_empty() {
+                return Err(invalid());
+            }
+            parent_indent = Some(indent);
+            start = line + 1;
+            end = block_end;
+            continue;
+        }
+
+        if value.starts_with('[') {
+            let open = lines[line].find('[').ok_or_else(invalid)?;
+            let close = open + lines[line][open..].find(']').ok_or_else(invalid)?;
+            let separator = if lines[line][open + 1..close].trim().is_empty() {
+                ""
+            } else {
+                ", "
+            };
+            lines[line].insert_str(close, &format!("{}{}", separator, values.join(", ")));
+        } else if value.is_empty() {
+            let is_item = |i: usize| lines[i].trim_start().starts_with("- ");
+            if (line + 1..block_end).any(|i| indentation(&lines[i]).is_some() && !is_item(i)) {
+                return Err(invalid());
+            }
+            let items = (line + 1..block_end)
+                .filter(|&i| is_item(i))
+                .collect::<Vec<_>>();
+            match (items.first(), items.last()) {
+                (Some(&first), Some(&last)) => {
+                    let pad = " ".repeat(indentation(&lines[first]).unwrap_or(indent + 2));
+                    let new = values.iter().map(|value| format!("{}- {}", pad, value));
+                    lines.splice(last + 1..last + 1, new);
+                }
+                _ => {
+                    let pad = " ".repeat(indent);
+                    lines[line] = format!("{}{}: [{}]", pad, key, values.join(", "));
+                }
+            }
+        } else {
+            return Err(invalid());
+        }
+        return Ok(lines.join("\n") + "\n");
+    }
+    Err(invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use yaml_rust::{Yaml, YamlLoader};
+
+    use super::{add_entries, check, insert_entries, Import, Issue};
+    use crate::document::to_yaml_string;
+    use crate::filetypes::FileTypes;
+
+    #[test]
+    fn embedded_database_is_clean() {
+        let issues = check(&FileTypes::from_embedded().unwrap());
+        let warnings = issues
+            .iter()
+            .filter(|issue| !issue.is_note())
+            .map(|issue| issue.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(Vec::<String>::new(), warnings);
+    }
+
+    #[test]
+    fn check_entries() {
+        let filetypes = FileTypes::from_string(
+            "
+                core:
+                  directory: [$di, $xx]
+                clojure: [.clj, .cljs, cljc]
+                hg: [.hgrc, hgrc]
+                broken: ['a:b', 'c=d', ' e']
+                markup: [.md]
+                text: [README.md]
+            ",
+        )
+        .unwrap();
+
+        let issues = check(&filetypes)
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "broken: \" e\" has leading or trailing whitespace",
+                "core.directory: unknown core code '$xx'",
+                "broken: \"a:b\" contains characters that are not allowed in LS_COLORS",
+                "broken: \"c=d\" contains characters that are not allowed in LS_COLORS",
+                "clojure: 'cljc' looks like an extension, but has no leading dot",
+                "markup: '.md' is overridden by 'README.md' from text",
+            ],
+            issues
+        );
+        assert!(matches!(check(&filetypes)[5], Issue::Shadowed { .. }));
+    }
+
+    #[test]
+    fn import_skips_existing_entries() {
+        let filetypes =
+            FileTypes::from_string("media:\n  image: [.png]\n  audio: [.mp3]\n").unwrap();
+        let image = ["media".to_string(), "image".to_string()];
+        let video = ["media".to_string(), "video".to_string()];
+
+        let mut import = Import::default();
+        import.add(&filetypes, &image, ".png");
+        import.add(&filetypes, &video, ".mp3");
+        import.add(&filetypes, &image, ".gif");
+        import.add(&filetypes, &image, ".gif");
+        import.add(&filetypes, &video, ".gif");
+
+        assert_eq!(
+            vec![(image.to_vec(), vec![".gif".to_string()])],
+            import.entries
+        );
+        assert_eq!(
+            vec![
+                (".png".to_string(), image.to_vec()),
+                (
+                    ".mp3".to_string(),
+                    vec!["media".to_string(), "audio".to_string()]
+                ),
+                (".gif".to_string(), image.to_vec()),
+            ],
+            import.skipped
+        );
+    }
+
+    #[test]
+    fn add_to_new_and_existing_categories() {
+        let mut doc = YamlLoader::load_from_str("media:\n  audio: [.mp3]\n")
+            .unwrap()
+            .pop()
+            .unwrap();
+
+        add_entries(
+            &mut doc,
+            &["media".into(), "audio".into()],
+            &[".ogg".into()],
+        )
+        .unwrap();
+        add_entries(
+            &mut doc,
+            &["media".into(), "video".into()],
+            &[".mkv".into()],
+        )
+        .unwrap();
+        assert!(add_entries(&mut doc, &["media".into()], &[".x".into()]).is_err());
+
+        assert_eq!(
+            "media:\n  audio:\n    - \".mp3\"\n    - \".ogg\"\n  video:\n    - \".mkv\"\n",
+            to_yaml_string(&doc).unwrap()
+        );
+
+        let mut empty = Yaml::Null;
+        add_entries(&mut empty, &["text".into()], &["README".into()]).unwrap();
+        assert_eq!("text:\n  - README\n", to_yaml_string(&empty).unwrap());
+    }
+
+    #[test]
+    fn insert_keeps_comments() {
+        let contents = "\
+# The database
+text:
+  special:
+    - README
+    # more names
+    - TODO
+
+  other: [.txt] # plain text
+media:
+  audio: [] # empty
+  3d: # models
+    - .obj
+";
+        let insert = |contents: &str, category: &[&str], entries: &[&str]| {
+            let category = category.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+            let entries = entries.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            insert_entries(contents, &category, &entries)
+        };
+
+        let contents = insert(contents, &["text", "special"], &["NEWS"]).unwrap();
+        let contents = insert(&contents, &["text", "other"], &[".log", "*.txt.*"]).unwrap();
+        let contents = insert(&contents, &["media", "audio"], &[".ogg"]).unwrap();
+        let contents = insert(&contents, &["media", "3d"], &[".stl"]).unwrap();
+        let contents = insert(&contents, &["media", "video", "clips"], &[".mkv"]).unwrap();
+        let contents = insert(&contents, &["archives"], &[".zip", "1984"]).unwrap();
+        assert_eq!(
+            "\
+# The database
+text:
+  special:
+    - README
+    # more names
+    - TODO
+    - NEWS
+
+  other: [.txt, .log, \"*.txt.*\"] # plain text
+media:
+  audio: [.ogg] # empty
+  3d: # models
+    - .obj
+    - .stl
+  video:
+    clips: [.mkv]
+
+archives: [.zip, \"1984\"]
+",
+            contents
+        );
+        assert!(FileTypes::from_string(&contents).is_ok());
+
+        assert!(insert(&contents, &["media"], &[".x"]).is_err());
+        assert!(insert(&contents, &["text", "other", "x"], &[".x"]).is_err());
+        assert_eq!(
+            "text: [README]\n",
+            insert("", &["text"], &["README"]).unwrap()
+        );
+    }
+}
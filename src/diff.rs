@@ -0,0 +1,238 @@
+use std::io::{self, Write};
+
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+use crate::document::DocumentFormat;
+use crate::error::{Result, VividError};
+use crate::ls_colors::LsColors;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added(String),
+    Removed(String),
+    Changed(String, String),
+}
+
+impl Change {
+    fn name(&self) -> &'static str {
+        match self {
+            Change::Added(_) => "added",
+            Change::Removed(_) => "removed",
+            Change::Changed(_, _) => "changed",
+        }
+    }
+
+    fn old_style(&self) -> Option<&str> {
+        match self {
+            Change::Added(_) => None,
+            Change::Removed(old) | Change::Changed(old, _) => Some(old),
+        }
+    }
+
+    fn new_style(&self) -> Option<&str> {
+        match self {
+            Change::Removed(_) => None,
+            Change::Added(new) | Change::Changed(_, new) => Some(new),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffEntry {
+    pub category: String,
+    pub key: String,
+    pub change: Change,
+}
+
+/// The SGR parameters of a style in a canonical form, so that equivalent
+/// styles compare equal: leading zeros are stripped (`01` is `1`, an empty
+/// parameter is `0`) and a leading reset is dropped (`0;31` is `31`)
+fn normalize(style: &str) -> Vec<String> {
+    let mut parameters = style
+        .split(';')
+        .map(|p| match p.trim_start_matches('0') {
+            "" => "0".to_string(),
+            p => p.to_string(),
+        })
+        .collect::<Vec<_>>();
+    if parameters.len() > 1 && parameters[0] == "0" {
+        parameters.remove(0);
+    }
+    parameters
+}
+
+/// Compare two `LS_COLORS` mappings key by key. Styles that only differ in
+/// their notation (`01;34` and `1;34`) are not reported.
+///
+/// The `categorize` function is used to assign a category to each key. The
+/// entries are sorted by category and then by key.
+pub fn diff<F>(old: &LsColors, new: &LsColors, categorize: F) -> Vec<DiffEntry>
+where
+    F: Fn(&str) -> String,
+{
+    let mut entries = Vec::new();
+
+    for (key, old_style) in old.iter() {
+        let change = match new.get(key) {
+            None => Change::Removed(old_style.into()),
+            Some(new_style) if normalize(new_style) != normalize(old_style) => {
+                Change::Changed(old_style.into(), new_style.into())
+            }
+            Some(_) => continue,
+        };
+        entries.push(DiffEntry {
+            category: categorize(key),
+            key: key.into(),
+            change,
+        });
+    }
+
+    for (key, new_style) in new.iter().filter(|(key, _)| old.get(key).is_none()) {
+        entries.push(DiffEntry {
+            category: categorize(key),
+            key: key.into(),
+            change: Change::Added(new_style.into()),
+        });
+    }
+
+    entries.sort_by(|a, b| (&a.category, &a.key).cmp(&(&b.category, &b.key)));
+    entries
+}
+
+fn sample(style: &str, key: &str) -> String {
+    format!("\x1b[{}m{}\x1b[0m", style, key)
+}
+
+pub fn write_text(out: &mut dyn Write, entries: &[DiffEntry]) -> io::Result<()> {
+    let mut current_category = None;
+    for entry in entries {
+        if current_category != Some(&entry.category) {
+            if current_category.is_some() {
+                writeln!(out)?;
+            }
+            writeln!(out, "{}", entry.category)?;
+            current_category = Some(&entry.category);
+        }
+
+        match &entry.change {
+            Change::Added(new) => writeln!(out, "  + {}", sample(new, &entry.key))?,
+            Change::Removed(old) => writeln!(out, "  - {}", sample(old, &entry.key))?,
+            Change::Changed(old, new) => writeln!(
+                out,
+                "  ~ {} -> {}",
+                sample(old, &entry.key),
+                sample(new, &entry.key)
+            )?,
+        }
+    }
+
+    let count = |name| entries.iter().filter(|e| e.change.name() == name).count();
+    if !entries.is_empty() {
+        writeln!(out)?;
+    }
+    writeln!(
+        out,
+        "{} added, {} removed, {} changed",
+        count("added"),
+        count("removed"),
+        count("changed")
+    )
+}
+
+pub fn write_json(out: &mut dyn Write, entries: &[DiffEntry]) -> Result<()> {
+    let string = |s: &str| Yaml::String(s.to_string());
+    let optional = |s: Option<&str>| s.map_or(Yaml::Null, string);
+
+    let list = entries
+        .iter()
+        .map(|entry| {
+            let mut item = Hash::new();
+            item.insert(string("category"), string(&entry.category));
+            item.insert(string("key"), string(&entry.key));
+            item.insert(string("change"), string(entry.change.name()));
+            item.insert(string("old"), optional(entry.change.old_style()));
+            item.insert(string("new"), optional(entry.change.new_style()));
+            Yaml::Hash(item)
+        })
+        .collect();
+
+    let json = DocumentFormat::Json.to_string(&Yaml::Array(list))?;
+    write!(out, "{}", json).map_err(VividError::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, normalize, write_json, Change};
+    use crate::ls_colors::LsColors;
+
+    #[test]
+    fn added_removed_changed() {
+        let old = LsColors::parse("di=34:*.rs=31:*.md=1").unwrap();
+        let new = LsColors::parse("di=34:*.rs=32:*.zip=35").unwrap();
+
+        let entries = diff(&old, &new, |key| {
+            if key.starts_with("*.") {
+                "files"
+            } else {
+                "core"
+            }
+            .to_string()
+        });
+
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| (e.key.as_str(), e.change.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("*.md", Change::Removed("1".into())),
+                ("*.rs", Change::Changed("31".into(), "32".into())),
+                ("*.zip", Change::Added("35".into())),
+            ],
+            summary
+        );
+    }
+
+    #[test]
+    fn equivalent_styles() {
+        assert_eq!(normalize("01;34"), normalize("1;34"));
+        assert_eq!(normalize("0;31"), normalize("31"));
+        assert_eq!(normalize("38;5;0"), normalize("0;38;5;00"));
+        assert_ne!(normalize("1;34"), normalize("34"));
+
+        let old = LsColors::parse("di=34:*.rs=0;31").unwrap();
+        let new = LsColors::parse("di=01;34:*.rs=31").unwrap();
+        let entries = diff(&old, &new, |_| "files".to_string());
+        assert_eq!(
+            vec![("di", Change::Changed("34".into(), "01;34".into()))],
+            entries
+                .iter()
+                .map(|e| (e.key.as_str(), e.change.clone()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn json_output() {
+        let old = LsColors::parse("di=34").unwrap();
+        let new = LsColors::parse("di=1;34").unwrap();
+        let entries = diff(&old, &new, |_| "core".to_string());
+
+        let mut out = Vec::new();
+        write_json(&mut out, &entries).unwrap();
+        assert_eq!(
+            r#"[
+  {
+    "category": "core",
+    "key": "di",
+    "change": "changed",
+    "old": "34",
+    "new": "1;34"
+  }
+]
+"#,
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use yaml_rust::yaml::Hash;
+use yaml_rust::{Yaml, YamlEmitter, YamlLoader};
+
+use crate::error::{Result, VividError};
+
+/// The file formats that themes and filetypes databases can be written in.
+/// All of them are parsed into the same YAML document tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocumentFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl DocumentFormat {
+    pub const NAMES: [&'static str; 3] = ["yaml", "toml", "json"];
+
+    /// The file extensions, in the order in which they are tried when looking
+    /// for a file by name
+    pub const EXTENSIONS: [&'static str; 4] = ["yml", "yaml", "toml", "json"];
+
+    /// The format of a file with this extension, if it is one of [`Self::EXTENSIONS`]
+    pub fn from_extension(extension: &str) -> Option<DocumentFormat> {
+        match extension {
+            "yml" | "yaml" => Some(DocumentFormat::Yaml),
+            "toml" => Some(DocumentFormat::Toml),
+            "json" => Some(DocumentFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// The format of a file, YAML unless the extension says otherwise
+    pub fn from_path(path: &Path) -> DocumentFormat {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(DocumentFormat::from_extension)
+            .unwrap_or(DocumentFormat::Yaml)
+    }
+
+    pub fn parse(self, contents: &str) -> Result<Yaml> {
+        match self {
+            DocumentFormat::Yaml => Ok(YamlLoader::load_from_str(contents)?
+                .pop()
+                .unwrap_or(Yaml::Null)),
+            DocumentFormat::Toml => {
+                let value = toml::from_str::<toml::Value>(contents)
+                    .map_err(|e| VividError::TomlParsingError(e.to_string()))?;
+                Ok(from_toml(value))
+            }
+            DocumentFormat::Json => {
+                let value = serde_json::from_str::<serde_json::Value>(contents)
+                    .map_err(|e| VividError::JsonParsingError(e.to_string()))?;
+                Ok(from_json(value))
+            }
+        }
+    }
+
+    /// Serialize a document. Comments and the formatting of the source are
+    /// not kept, but everything else is: the key order and all values.
+    pub fn to_string(self, doc: &Yaml) -> Result<String> {
+        match self {
+            DocumentFormat::Yaml => to_yaml_string(doc),
+            DocumentFormat::Toml => {
+                let value = to_toml(doc)?;
+                if !value.is_table() {
+                    return Err(VividError::UnsupportedValue(
+                        "TOML",
+                        "a document that is not a table".into(),
+                    ));
+                }
+                toml::to_string(&value)
+                    .map_err(|e| VividError::UnsupportedValue("TOML", e.to_string()))
+            }
+            DocumentFormat::Json => {
+                let json = serde_json::to_string_pretty(&to_json(doc)?)
+                    .map_err(|e| VividError::UnsupportedValue("JSON", e.to_string()))?;
+                Ok(format!("{}\n", json))
+            }
+        }
+    }
+}
+
+impl FromStr for DocumentFormat {
+    type Err = VividError;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "yaml" | "yml" => Ok(DocumentFormat::Yaml),
+            "toml" => Ok(DocumentFormat::Toml),
+            "json" => Ok(DocumentFormat::Json),
+            _ => Err(VividError::UnknownDocumentFormat(s.to_string())),
+        }
+    }
+}
+
+/// Serialize a YAML document, without the document start marker
+pub fn to_yaml_string(doc: &Yaml) -> Result<String> {
+    let mut out = String::new();
+    YamlEmitter::new(&mut out)
+        .dump(doc)
+        .map_err(|_| VividError::UnexpectedYamlType)?;
+    let out = out.strip_prefix("---\n").unwrap_or(&out);
+    Ok(format!("{}\n", out))
+}
+
+fn from_toml(value: toml::Value) -> Yaml {
+    match value {
+        toml::Value::String(s) => Yaml::String(s),
+        toml::Value::Integer(i) => Yaml::Integer(i),
+        toml::Value::Float(f) => Yaml::Real(f.to_string()),
+        toml::Value::Boolean(b) => Yaml::Boolean(b),
+        toml::Value::Datetime(d) => Yaml::String(d.to_string()),
+        toml::Value::Array(array) => Yaml::Array(array.into_iter().map(from_toml).collect()),
+        toml::Value::Table(table) => Yaml::Hash(
+            table
+                .into_iter()
+                .map(|(key, value)| (Yaml::String(key), from_toml(value)))
+                .collect::<Hash>(),
+        ),
+    }
+}
+
+fn from_json(value: serde_json::Value) -> Yaml {
+    match value {
+        serde_json::Value::Null => Yaml::Null,
+        serde_json::Value::Bool(b) => Yaml::Boolean(b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Yaml::Integer(i),
+            None => Yaml::Real(n.to_string()),
+        },
+        serde_json::Value::String(s) => Yaml::String(s),
+        serde_json::Value::Array(array) => Yaml::Array(array.into_iter().map(from_json).collect()),
+        serde_json::Value::Object(object) => Yaml::Hash(
+            object
+                .into_iter()
+                .map(|(key, value)| (Yaml::String(key), from_json(value)))
+                .collect::<Hash>(),
+        ),
+    }
+}
+
+/// The key of a mapping entry. YAML allows any value as a key, TOML and JSON
+/// only strings.
+fn string_key(key: &Yaml, format: &'static str) -> Result<String> {
+    key.as_str()
+        .map(String::from)
+        .ok_or_else(|| VividError::UnsupportedValue(format, format!("the key {:?}", key)))
+}
+
+fn real(value: &str, format: &'static str) -> Result<f64> {
+    value
+        .parse()
+        .map_err(|_| VividError::UnsupportedValue(format, format!("the number '{}'", value)))
+}
+
+fn to_toml(doc: &Yaml) -> Result<toml::Value> {
+    Ok(match doc {
+        Yaml::String(s) => toml::Value::String(s.clone()),
+        Yaml::Integer(i) => toml::Value::Integer(*i),
+        Yaml::Real(r) => toml::Value::Float(real(r, "TOML")?),
+        Yaml::Boolean(b) => toml::Value::Boolean(*b),
+        Yaml::Array(array) => toml::Value::Array(array.iter().map(to_toml).collect::<Result<_>>()?),
+        Yaml::Hash(map) => toml::Value::Table(
+            map.iter()
+                .map(|(key, value)| Ok((string_key(key, "TOML")?, to_toml(value)?)))
+                .collect::<Result<_>>()?,
+        ),
+        Yaml::Null => {
+            return Err(VividError::UnsupportedValue(
+                "TOML",
+                "an empty value".into(),
+            ))
+        }
+        Yaml::Alias(_) | Yaml::BadValue => return Err(VividError::UnexpectedYamlType),
+    })
+}
+
+fn to_json(doc: &Yaml) -> Result<serde_json::Value> {
+    Ok(match doc {
+        Yaml::String(s) => serde_json::Value::String(s.clone()),
+        Yaml::Integer(i) => serde_json::Value::from(*i),
+        Yaml::Real(r) => serde_json::Number::from_f64(real(r, "JSON")?)
+            .map(serde_json::Value::Number)
+            .ok_or_else(|| VividError::UnsupportedValue("JSON", format!("the number '{}'", r)))?,
+        Yaml::Boolean(b) => serde_json::Value::Bool(*b),
+        Yaml::Array(array) => {
+            serde_json::Value::Array(array.iter().map(to_json).collect::<Result<_>>()?)
+        }
+        Yaml::Hash(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(key, value)| Ok((string_key(key, "JSON")?, to_json(value)?)))
+                .collect::<Result<_>>()?,
+        ),
+        Yaml::Null => serde_json::Value::Null,
+        Yaml::Alias(_) | Yaml::BadValue => return Err(VividError::UnexpectedYamlType),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::DocumentFormat;
+
+    const THEME: &str = "meta:
+  name: Example
+colors:
+  black: \"000000\"
+  red: ff0000
+core:
+  directory:
+    foreground: red
+    font-style:
+      - bold
+      - underline
+    special:
+      foreground: black
+";
+
+    #[test]
+    fn format_from_path() {
+        let format = |path| DocumentFormat::from_path(Path::new(path));
+        assert_eq!(DocumentFormat::Yaml, format("themes/molokai.yml"));
+        assert_eq!(DocumentFormat::Yaml, format("themes/molokai.yaml"));
+        assert_eq!(DocumentFormat::Toml, format("themes/molokai.toml"));
+        assert_eq!(DocumentFormat::Json, format("filetypes.json"));
+        assert_eq!(DocumentFormat::Yaml, format("molokai"));
+    }
+
+    #[test]
+    fn convert() {
+        let doc = DocumentFormat::Yaml.parse(THEME).unwrap();
+
+        let toml = DocumentFormat::Toml.to_string(&doc).unwrap();
+        assert_eq!(
+            r#"[meta]
+name = "Example"
+
+[colors]
+black = "000000"
+red = "ff0000"
+
+[core.directory]
+foreground = "red"
+font-style = ["bold", "underline"]
+
+[core.directory.special]
+foreground = "black"
+"#,
+            toml
+        );
+
+        let json = DocumentFormat::Json.to_string(&doc).unwrap();
+        assert!(json.starts_with("{\n  \"meta\": {\n    \"name\": \"Example\"\n  },"));
+
+        for format in &[DocumentFormat::Toml, DocumentFormat::Json] {
+            let converted = format.to_string(&doc).unwrap();
+            assert_eq!(doc, format.parse(&converted).unwrap());
+            let yaml = DocumentFormat::Yaml
+                .to_string(&format.parse(&converted).unwrap())
+                .unwrap();
+            assert_eq!(THEME, yaml);
+        }
+    }
+
+    #[test]
+    fn unsupported_values() {
+        let doc = DocumentFormat::Yaml.parse("colors:\n  red:\n").unwrap();
+        assert!(DocumentFormat::Toml.to_string(&doc).is_err());
+        assert_eq!(
+            "{\n  \"colors\": {\n    \"red\": null\n  }\n}\n",
+            DocumentFormat::Json.to_string(&doc).unwrap()
+        );
+
+        let doc = DocumentFormat::Yaml.parse("1: one").unwrap();
+        assert!(DocumentFormat::Json.to_string(&doc).is_err());
+        assert!(DocumentFormat::Toml.parse("a = ").is_err());
+        assert!(DocumentFormat::Json.parse("{").is_err());
+    }
+}
@@ -13,6 +13,7 @@ pub enum VividError {
     ColorParseError(String),
     DuplicateFileType(String),
     CouldNotLoadDatabaseFrom(String),
+    CouldNotReadMimePackage(String),
     CouldNotFindTheme(String),
     CouldNotLoadTheme(String),
     NoThemeProvided,
@@ -20,6 +21,28 @@ pub enum VividError {
     CouldNotFindStyleFor(String),
     UnknownColor(String),
     InvalidFileName(String),
+    InvalidLsColorsEntry(String),
+    UnknownColorMode(String),
+    UnknownOutputFormat(String),
+    InvalidConfig(String, String),
+    InvalidEnvironmentVariable(String, String),
+    InvalidThemeMeta(String, String),
+    InvalidMimeInfo(String),
+    InvalidDatabaseCategory(String),
+    DatabaseCheckFailed(usize),
+    TomlParsingError(String),
+    JsonParsingError(String),
+    UnknownDocumentFormat(String),
+    UnsupportedValue(&'static str, String),
+    UnknownSchema(String),
+    UnknownPaletteFormat(String),
+    InvalidPalette(String),
+    InvalidBase16Scheme(String),
+    NoRgbColors,
+    NothingToApply(String),
+    NoToolStyles,
+    ToolsNeedShellFormat(String),
+    NotAnLsColorsFormat(String),
 }
 
 impl Display for VividError {
@@ -35,6 +58,13 @@ impl Display for VividError {
             VividError::CouldNotLoadDatabaseFrom(path) => {
                 write!(fmt, "Could not load filetypes database from '{}'.", path)
             }
+            VividError::CouldNotReadMimePackage(path) => {
+                write!(
+                    fmt,
+                    "Could not read shared-mime-info package from '{}'.",
+                    path
+                )
+            }
             VividError::CouldNotFindTheme(name) => write!(fmt, "Could not find theme '{}'.", name),
             VividError::CouldNotLoadTheme(path) => write!(fmt, "Could not load theme '{}'.", path),
             VividError::NoThemeProvided => write!(
@@ -49,6 +79,74 @@ impl Display for VividError {
             VividError::InvalidFileName(file_name) => {
                 write!(fmt, "Invalid file name '{}'", file_name)
             }
+            VividError::InvalidLsColorsEntry(entry) => {
+                write!(fmt, "Invalid LS_COLORS entry '{}'", entry)
+            }
+            VividError::UnknownColorMode(mode) => write!(fmt, "Unknown color mode '{}'", mode),
+            VividError::UnknownOutputFormat(format) => {
+                write!(fmt, "Unknown output format '{}'", format)
+            }
+            VividError::InvalidConfig(path, message) => {
+                write!(fmt, "Invalid config file '{}': {}", path, message)
+            }
+            VividError::InvalidEnvironmentVariable(var, value) => {
+                write!(
+                    fmt,
+                    "Invalid value '{}' for environment variable {}",
+                    value, var
+                )
+            }
+            VividError::InvalidThemeMeta(key, value) => {
+                write!(fmt, "Invalid theme metadata '{}: {}'", key, value)
+            }
+            VividError::InvalidMimeInfo(message) => {
+                write!(fmt, "Could not parse shared-mime-info package: {}", message)
+            }
+            VividError::InvalidDatabaseCategory(category) => write!(
+                fmt,
+                "Can not add file types to category '{}', it is not a list",
+                category
+            ),
+            VividError::DatabaseCheckFailed(count) => {
+                write!(fmt, "Found {} problem(s) in the filetypes database", count)
+            }
+            VividError::TomlParsingError(message) => write!(fmt, "{}", message),
+            VividError::JsonParsingError(message) => write!(fmt, "{}", message),
+            VividError::UnknownDocumentFormat(format) => {
+                write!(fmt, "Unknown file format '{}'", format)
+            }
+            VividError::UnsupportedValue(format, what) => {
+                write!(fmt, "{} can not represent {}", format, what)
+            }
+            VividError::UnknownSchema(name) => write!(fmt, "Unknown schema '{}'", name),
+            VividError::UnknownPaletteFormat(format) => {
+                write!(fmt, "Unknown color scheme format '{}'", format)
+            }
+            VividError::InvalidPalette(message) => {
+                write!(fmt, "Could not read color scheme: {}", message)
+            }
+            VividError::InvalidBase16Scheme(message) => {
+                write!(fmt, "Could not read Base16 scheme: {}", message)
+            }
+            VividError::NoRgbColors => write!(fmt, "The theme has no RGB colors."),
+            VividError::NothingToApply(theme) => write!(
+                fmt,
+                "The theme '{}' has only ansi: colors, nothing to apply. Use --reset to restore the terminal palette.",
+                theme
+            ),
+            VividError::NoToolStyles => {
+                write!(fmt, "The theme has no styles for the tools of --tools.")
+            }
+            VividError::ToolsNeedShellFormat(format) => write!(
+                fmt,
+                "--tools writes shell statements, it can not be used with --format {}. Use --format sh or fish.",
+                format
+            ),
+            VividError::NotAnLsColorsFormat(format) => write!(
+                fmt,
+                "The output format '{}' is not an LS_COLORS expression.",
+                format
+            ),
         }
     }
 }
@@ -0,0 +1,302 @@
+use std::io::Write;
+
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+use crate::color::{Color, ColorType};
+use crate::document::DocumentFormat;
+use crate::error::{Result, VividError};
+use crate::filetypes::FileTypes;
+use crate::theme::{Style, Theme};
+use crate::types::{Category, FileType};
+
+/// Version of the JSON/TOML export format, see `schemas/export.json`.
+/// Incremented on incompatible changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The resolved style of one file type
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// The `LS_COLORS` key or glob pattern
+    pub key: FileType,
+    pub category: Category,
+    pub style: Style,
+    /// The SGR parameters as used in `LS_COLORS`
+    pub sgr: String,
+}
+
+/// Resolve the style of every file type, in the same order as `LS_COLORS`
+pub fn resolve(filetypes: &FileTypes, theme: &Theme) -> Result<Vec<Entry>> {
+    let mut entries = filetypes
+        .mapping
+        .iter()
+        .chain(filetypes.globs.iter())
+        .map(|(key, category)| {
+            Ok(Entry {
+                key: key.clone(),
+                category: category.clone(),
+                style: theme.resolve_style(category)?,
+                sgr: theme.get_style(category)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    entries.sort_unstable_by(|a, b| (a.key.len(), &a.key).cmp(&(b.key.len(), &b.key)));
+    Ok(entries)
+}
+
+fn colors(style: &Style) -> [(&'static str, Option<Color>, ColorType); 3] {
+    [
+        ("foreground", style.foreground, ColorType::Foreground),
+        ("background", style.background, ColorType::Background),
+        (
+            "underline-color",
+            style.underline_color,
+            ColorType::Underline,
+        ),
+    ]
+}
+
+fn string(s: &str) -> Yaml {
+    Yaml::String(s.to_string())
+}
+
+fn string_list<'a>(items: impl Iterator<Item = &'a str>) -> Yaml {
+    Yaml::Array(items.map(string).collect())
+}
+
+/// The export as a document. TOML has no null, so `nulls` decides whether
+/// missing colors are written as null or left out.
+fn document(theme_name: &str, theme: &Theme, entries: &[Entry], nulls: bool) -> Yaml {
+    let mut items = Vec::new();
+    for entry in entries {
+        let mut item = Hash::new();
+        item.insert(string("key"), string(&entry.key));
+        item.insert(
+            string("category"),
+            string_list(entry.category.iter().map(String::as_str)),
+        );
+        item.insert(
+            string("theme-node"),
+            string_list(entry.style.node.iter().map(String::as_str)),
+        );
+        item.insert(
+            string("font-styles"),
+            string_list(entry.style.font_style.names()),
+        );
+        for (name, color, color_type) in colors(&entry.style) {
+            let value = match color {
+                Some(color) => {
+                    let mut value = Hash::new();
+                    value.insert(string("color"), string(&color.to_string()));
+                    value.insert(
+                        string("sgr"),
+                        Yaml::String(theme.get_color_style(color, color_type)),
+                    );
+                    Yaml::Hash(value)
+                }
+                None if nulls => Yaml::Null,
+                None => continue,
+            };
+            item.insert(string(name), value);
+        }
+        item.insert(string("sgr"), string(&entry.sgr));
+        items.push(Yaml::Hash(item));
+    }
+
+    let mut doc = Hash::new();
+    doc.insert(string("version"), Yaml::Integer(FORMAT_VERSION.into()));
+    doc.insert(string("theme"), string(theme_name));
+    doc.insert(string("color-mode"), string(theme.color_mode().name()));
+    doc.insert(string("entries"), Yaml::Array(items));
+    Yaml::Hash(doc)
+}
+
+pub fn write_json(
+    out: &mut dyn Write,
+    theme_name: &str,
+    theme: &Theme,
+    entries: &[Entry],
+) -> Result<()> {
+    let doc = document(theme_name, theme, entries, true);
+    write!(out, "{}", DocumentFormat::Json.to_string(&doc)?).map_err(VividError::IoError)
+}
+
+pub fn write_toml(
+    out: &mut dyn Write,
+    theme_name: &str,
+    theme: &Theme,
+    entries: &[Entry],
+) -> Result<()> {
+    let doc = document(theme_name, theme, entries, false);
+    write!(out, "{}", DocumentFormat::Toml.to_string(&doc)?).map_err(VividError::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve, write_json, write_toml};
+    use crate::color::ColorMode;
+    use crate::filetypes::FileTypes;
+    use crate::palette::Palette;
+    use crate::theme::Theme;
+
+    fn output(toml: bool) -> String {
+        let filetypes = FileTypes::from_string(
+            "
+                core:
+                  directory: [$di]
+                programming:
+                  source:
+                    rust: [.rs]
+            ",
+        )
+        .unwrap();
+        let theme = Theme::from_string(
+            "
+                colors:
+                  blue: '0000ff'
+                core:
+                  directory:
+                    foreground: blue
+                    font-style: [bold, underline]
+                programming:
+                  foreground: 'ansi:red'
+            ",
+            ColorMode::BitDepth8,
+        )
+        .unwrap();
+
+        let entries = resolve(&filetypes, &theme).unwrap();
+        let mut out = Vec::new();
+        if toml {
+            write_toml(&mut out, "example", &theme, &entries).unwrap();
+        } else {
+            write_json(&mut out, "example", &theme, &entries).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            r##"{
+  "version": 1,
+  "theme": "example",
+  "color-mode": "8-bit",
+  "entries": [
+    {
+      "key": "di",
+      "category": [
+        "core",
+        "directory"
+      ],
+      "theme-node": [
+        "core",
+        "directory"
+      ],
+      "font-styles": [
+        "bold",
+        "underline"
+      ],
+      "foreground": {
+        "color": "#0000ff",
+        "sgr": "38;5;21"
+      },
+      "background": null,
+      "underline-color": null,
+      "sgr": "1;4;38;5;21"
+    },
+    {
+      "key": "*.rs",
+      "category": [
+        "programming",
+        "source",
+        "rust"
+      ],
+      "theme-node": [
+        "programming"
+      ],
+      "font-styles": [
+        "regular"
+      ],
+      "foreground": {
+        "color": "ansi:red",
+        "sgr": "31"
+      },
+      "background": null,
+      "underline-color": null,
+      "sgr": "0;31"
+    }
+  ]
+}
+"##,
+            output(false)
+        );
+    }
+
+    #[test]
+    fn toml() {
+        assert_eq!(
+            r##"version = 1
+theme = "example"
+color-mode = "8-bit"
+
+[[entries]]
+key = "di"
+category = ["core", "directory"]
+theme-node = ["core", "directory"]
+font-styles = ["bold", "underline"]
+sgr = "1;4;38;5;21"
+
+[entries.foreground]
+color = "#0000ff"
+sgr = "38;5;21"
+
+[[entries]]
+key = "*.rs"
+category = ["programming", "source", "rust"]
+theme-node = ["programming"]
+font-styles = ["regular"]
+sgr = "0;31"
+
+[entries.foreground]
+color = "ansi:red"
+sgr = "31"
+"##,
+            output(true)
+        );
+    }
+
+    #[test]
+    fn terminal_palette() {
+        let filetypes = FileTypes::from_string(
+            "
+                core:
+                  directory: [$di]
+            ",
+        )
+        .unwrap();
+        let mut theme = Theme::from_string(
+            "
+                colors:
+                  navy: '123456'
+                core:
+                  directory:
+                    foreground: navy
+            ",
+            ColorMode::BitDepth8,
+        )
+        .unwrap();
+        let mut palette = Palette::default();
+        palette.colors.insert("blue", (0x12, 0x34, 0x56));
+        theme.set_terminal_palette(palette);
+
+        let entries = resolve(&filetypes, &theme).unwrap();
+        assert_eq!("0;38;5;4", entries[0].sgr);
+        let mut out = Vec::new();
+        write_json(&mut out, "example", &theme, &entries).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains(
+            r##""color": "#123456",
+        "sgr": "38;5;4""##
+        ));
+    }
+}
@@ -1,16 +1,42 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 
 use rust_embed::RustEmbed;
 use yaml_rust::yaml::YamlLoader;
 use yaml_rust::Yaml;
 
+use crate::database;
+use crate::document::DocumentFormat;
 use crate::error::{Result, VividError};
 use crate::types::{Category, FileType};
 use crate::util::load_yaml_file;
 
 pub struct FileTypes {
     pub mapping: HashMap<FileType, Category>,
+    /// Glob patterns like `*.tar.*` or `Makefile.*` that can not be expressed
+    /// as an `LS_COLORS` key
+    pub globs: HashMap<FileType, Category>,
+    /// Extension keys from categories marked with `case-insensitive: true`
+    pub case_insensitive: HashSet<FileType>,
+}
+
+/// Category option that makes extensions match in upper and title case, too
+const CASE_INSENSITIVE: &str = "case-insensitive";
+
+/// Characters that make a database entry a glob pattern instead of a suffix
+const GLOB_CHARACTERS: &[char] = &['*', '?', '[', '{'];
+
+/// Bracket sets and brace lists that would expand to more suffixes than this
+/// are kept as glob patterns
+const MAX_EXPANSIONS: usize = 64;
+
+/// Where a database entry ends up
+enum Key {
+    /// A core code like `di` or `*<suffix>` keys, more than one if the entry
+    /// lists alternatives like `.[ch]` or `.{yml,yaml}`
+    LsColors(Vec<FileType>),
+    /// A glob pattern that only some consumers understand
+    Glob(FileType),
 }
 
 #[derive(RustEmbed)]
@@ -18,46 +44,134 @@ pub struct FileTypes {
 struct ConfigAssets;
 
 impl FileTypes {
+    /// Load a database in the format given by the file extension (YAML, TOML or JSON)
     pub fn from_path(path: &Path) -> Result<FileTypes> {
         let contents = load_yaml_file(path)
             .map_err(|_| VividError::CouldNotLoadDatabaseFrom(path.to_string_lossy().into()))?;
-        Self::from_string(&contents)
+        Self::from_document(&DocumentFormat::from_path(path).parse(&contents)?)
     }
 
     pub fn from_embedded() -> Result<FileTypes> {
+        Self::from_string(&Self::embedded_contents()?)
+    }
+
+    /// The YAML source of the embedded default database
+    pub fn embedded_contents() -> Result<String> {
         let filetypes = ConfigAssets::get("filetypes.yml").unwrap();
 
-        let contents = std::str::from_utf8(&filetypes.data)
-            .map_err(|_| VividError::CouldNotLoadDatabaseFrom(String::from("embedded defaults")))?;
-        Self::from_string(contents)
+        std::str::from_utf8(&filetypes.data)
+            .map(String::from)
+            .map_err(|_| VividError::CouldNotLoadDatabaseFrom(String::from("embedded defaults")))
     }
 
-    fn from_string(contents: &str) -> Result<FileTypes> {
+    pub(crate) fn from_string(contents: &str) -> Result<FileTypes> {
         let docs = YamlLoader::load_from_str(contents)?;
-        let doc = &docs[0];
+        Self::from_document(&docs[0])
+    }
 
-        Self::get_mapping(doc, &vec![])
+    /// Print the malformed entries that `vivid database check` would report.
+    /// Notes about overridden suffixes are left to the command.
+    pub fn warn_about_issues(&self) {
+        for issue in database::check_malformed(self) {
+            eprintln!("Warning: {}", issue);
+        }
     }
 
-    fn get_code(filetype: &str) -> String {
-        if filetype.get(0..1) == Some("$") {
-            filetype[1..].into()
-        } else {
-            let mut s = String::from("*");
-            s.push_str(filetype);
-            s
+    pub fn from_document(doc: &Yaml) -> Result<FileTypes> {
+        Self::get_mapping(doc, &vec![], false)
+    }
+
+    /// Add upper and title case variants of extension keys (`*.jpg` gets
+    /// `*.JPG` and `*.Jpg`) for categories marked as case-insensitive, or for
+    /// all extensions if `all` is set
+    ///
+    /// GNU ls matches suffixes case-sensitively as soon as a suffix appears in
+    /// more than one case, so the variants have to be listed explicitly. Entries
+    /// that are present in the database are never replaced. The variants have
+    /// the same length as the original key, so the order in `LS_COLORS` still
+    /// lets longer keys like `*README.md` override `*.md`.
+    pub fn add_case_variants(&mut self, all: bool) {
+        let mut keys = self
+            .mapping
+            .keys()
+            .filter(|key| key.starts_with("*.") && (all || self.case_insensitive.contains(*key)))
+            .cloned()
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+
+        for key in keys {
+            let extension = &key[2..];
+            let mut title_case = extension.chars();
+            let title_case = title_case
+                .next()
+                .map(|first| first.to_uppercase().chain(title_case).collect::<String>())
+                .unwrap_or_default();
+
+            for variant in &[extension.to_uppercase(), title_case] {
+                let variant = format!("*.{}", variant);
+                if !self.mapping.contains_key(&variant) {
+                    let category = self.mapping[&key].clone();
+                    self.mapping.insert(variant, category);
+                }
+            }
         }
     }
 
-    fn get_mapping(value: &Yaml, category: &Category) -> Result<FileTypes> {
+    /// The category of a database entry (like `.rs` or `$di`), if it is
+    /// already part of the database
+    pub fn category_of(&self, filetype: &str) -> Option<&Category> {
+        match Self::get_key(filetype) {
+            Key::LsColors(codes) => codes.iter().find_map(|code| self.mapping.get(code)),
+            Key::Glob(glob) => self.globs.get(&glob),
+        }
+    }
+
+    fn get_key(filetype: &str) -> Key {
+        if let Some(code) = filetype.strip_prefix('$') {
+            return Key::LsColors(vec![code.into()]);
+        }
+
+        let suffix_key = |pattern: &str| {
+            if !pattern.contains(GLOB_CHARACTERS) {
+                Some(format!("*{}", pattern))
+            } else {
+                // `*<suffix>` is exactly what an `LS_COLORS` key matches
+                pattern
+                    .strip_prefix('*')
+                    .filter(|suffix| !suffix.contains(GLOB_CHARACTERS))
+                    .map(|_| pattern.to_string())
+            }
+        };
+        match expand(filetype).and_then(|patterns| patterns.iter().map(|p| suffix_key(p)).collect())
+        {
+            Some(keys) => Key::LsColors(keys),
+            None => Key::Glob(filetype.into()),
+        }
+    }
+
+    fn get_mapping(value: &Yaml, category: &Category, case_insensitive: bool) -> Result<FileTypes> {
         let mut mapping = HashMap::new();
+        let mut globs = HashMap::new();
+        let mut case_insensitive_keys = HashSet::new();
 
         match value {
             Yaml::Array(array) => {
                 for filetype in array {
                     if let Yaml::String(filetype) = filetype {
-                        let code = Self::get_code(filetype);
-                        let result = mapping.insert(code, category.clone());
+                        let result = match Self::get_key(filetype) {
+                            Key::LsColors(codes) => {
+                                let mut duplicate = None;
+                                for code in codes {
+                                    if case_insensitive {
+                                        case_insensitive_keys.insert(code.clone());
+                                    }
+                                    duplicate =
+                                        duplicate.or(mapping.insert(code, category.clone()));
+                                }
+                                duplicate
+                            }
+                            Key::Glob(glob) => globs.insert(glob, category.clone()),
+                        };
 
                         if result.is_some() {
                             return Err(VividError::DuplicateFileType(filetype.to_string()));
@@ -68,22 +182,37 @@ impl FileTypes {
                 }
             }
             Yaml::Hash(ref map) => {
+                let case_insensitive = match map.get(&Yaml::String(CASE_INSENSITIVE.into())) {
+                    Some(Yaml::Boolean(value)) => *value,
+                    _ => case_insensitive,
+                };
+
                 for (key, value) in map {
+                    if let (Yaml::String(key), Yaml::Boolean(_)) = (key, value) {
+                        if key == CASE_INSENSITIVE {
+                            continue;
+                        }
+                    }
+
                     let mut child_category = category.clone();
                     if let Yaml::String(key) = key {
                         child_category.push(key.clone());
                     }
-                    let child_mapping = Self::get_mapping(value, &child_category)?;
+                    let child_mapping =
+                        Self::get_mapping(value, &child_category, case_insensitive)?;
 
                     if let Some(filetype) = child_mapping
                         .mapping
                         .keys()
                         .find(|ft| mapping.contains_key(*ft))
+                        .or_else(|| child_mapping.globs.keys().find(|g| globs.contains_key(*g)))
                     {
                         return Err(VividError::DuplicateFileType(filetype.to_string()));
                     }
 
                     mapping.extend(child_mapping.mapping);
+                    globs.extend(child_mapping.globs);
+                    case_insensitive_keys.extend(child_mapping.case_insensitive);
                 }
             }
             _ => {
@@ -91,8 +220,99 @@ impl FileTypes {
             }
         }
 
-        Ok(FileTypes { mapping })
+        Ok(FileTypes {
+            mapping,
+            globs,
+            case_insensitive: case_insensitive_keys,
+        })
+    }
+}
+
+/// Expand the bracket sets (`[ch]`, `[0-9]`) and brace lists (`{yml,yaml}`)
+/// of a pattern into the patterns they stand for, or `None` if a set is
+/// negated or malformed or there would be too many of them
+fn expand(pattern: &str) -> Option<Vec<String>> {
+    let (start, end, alternatives) = match pattern.find(['[', '{']) {
+        None => return Some(vec![pattern.to_string()]),
+        Some(start) if pattern[start..].starts_with('[') => {
+            let end = start + pattern[start..].find(']')?;
+            (start, end, bracket_set(&pattern[start + 1..end])?)
+        }
+        Some(start) => {
+            let end = start + closing_brace(&pattern[start..])?;
+            (start, end, brace_list(&pattern[start + 1..end]))
+        }
+    };
+
+    let mut patterns = Vec::new();
+    for alternative in alternatives {
+        let rest = format!("{}{}", alternative, &pattern[end + 1..]);
+        for expanded in expand(&rest)? {
+            patterns.push(format!("{}{}", &pattern[..start], expanded));
+        }
+        if patterns.len() > MAX_EXPANSIONS {
+            return None;
+        }
+    }
+    Some(patterns)
+}
+
+/// The characters of a bracket set like `ch` or `0-9`
+fn bracket_set(set: &str) -> Option<Vec<String>> {
+    if set.is_empty() || set.starts_with(['!', '^']) {
+        return None;
+    }
+    let chars = set.chars().collect::<Vec<_>>();
+    let mut alternatives = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if i + 2 < chars.len() && chars[i + 1] == '-' {
+            if chars[i] > chars[i + 2]
+                || (chars[i + 2] as u32 - chars[i] as u32) as usize >= MAX_EXPANSIONS
+            {
+                return None;
+            }
+            alternatives.extend((chars[i]..=chars[i + 2]).map(String::from));
+            i += 3;
+        } else {
+            alternatives.push(chars[i].to_string());
+            i += 1;
+        }
+    }
+    Some(alternatives)
+}
+
+/// The position of the brace that closes the one `pattern` starts with
+fn closing_brace(pattern: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in pattern.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 1 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
     }
+    None
+}
+
+/// The alternatives of a brace list like `yml,yaml`, split at the commas that
+/// are not part of a nested list
+fn brace_list(list: &str) -> Vec<String> {
+    let mut alternatives = vec![String::new()];
+    let mut depth = 0;
+    for c in list.chars() {
+        match c {
+            ',' if depth == 0 => alternatives.push(String::new()),
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => {}
+        }
+        if c != ',' || depth > 0 {
+            alternatives.last_mut().unwrap().push(c);
+        }
+    }
+    alternatives
 }
 
 #[cfg(test)]
@@ -122,4 +342,78 @@ mod tests {
             ft.mapping["*.ext3"]
         );
     }
+
+    #[test]
+    fn globs() {
+        let ft = FileTypes::from_string(
+            "
+                archives: ['*.tar.*', .tar.gz]
+                unimportant: ['*~', '*.bak']
+                text: ['Makefile.*', 'README.[mr]*']
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(vec!["archives"], ft.mapping["*.tar.gz"]);
+        assert_eq!(vec!["unimportant"], ft.mapping["*~"]);
+        assert_eq!(vec!["unimportant"], ft.mapping["*.bak"]);
+        assert_eq!(3, ft.globs.len());
+        assert_eq!(vec!["archives"], ft.globs["*.tar.*"]);
+        assert_eq!(vec!["text"], ft.globs["Makefile.*"]);
+        assert_eq!(vec!["text"], ft.globs["README.[mr]*"]);
+
+        assert!(FileTypes::from_string("a: ['*.bak']\nb: [.bak]").is_err());
+        assert!(FileTypes::from_string("a: ['Makefile.*']\nb: ['Makefile.*']").is_err());
+    }
+
+    #[test]
+    fn expanded_globs() {
+        let ft = FileTypes::from_string(
+            "
+                source: ['*.[ch]', '.{yml,yaml}', 'x.{a,b{c,d}}', '*.[0-2]']
+                globs: ['*.[!o]', '*.{c', 'README.[mr]*', '.[a-zA-Z][a-zA-Z]']
+            ",
+        )
+        .unwrap();
+
+        let mut keys = ft.mapping.keys().collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(
+            vec!["*.0", "*.1", "*.2", "*.c", "*.h", "*.yaml", "*.yml", "*x.a", "*x.bc", "*x.bd"],
+            keys
+        );
+        assert_eq!(vec!["source"], *ft.category_of("*.[ch]").unwrap());
+        assert_eq!(4, ft.globs.len());
+
+        assert!(FileTypes::from_string("a: ['*.[ch]']\nb: [.c]").is_err());
+    }
+
+    #[test]
+    fn case_variants() {
+        let mut ft = FileTypes::from_string(
+            "
+                media:
+                  case-insensitive: true
+                  image: [.jpg, .JPG, .tar.gz]
+                  special: [README.md]
+
+                text: [.md, .txt]
+            ",
+        )
+        .unwrap();
+        ft.mapping.insert("*.Jpg".into(), vec!["explicit".into()]);
+
+        ft.add_case_variants(false);
+
+        assert_eq!(vec!["media", "image"], ft.mapping["*.TAR.GZ"]);
+        assert_eq!(vec!["media", "image"], ft.mapping["*.Tar.gz"]);
+        assert_eq!(vec!["explicit"], ft.mapping["*.Jpg"]);
+        assert!(!ft.mapping.contains_key("*README.MD"));
+        assert!(!ft.mapping.contains_key("*.MD"));
+        assert!(!ft.mapping.contains_key("*.case-insensitive"));
+
+        ft.add_case_variants(true);
+        assert_eq!(vec!["text"], ft.mapping["*.MD"]);
+        assert_eq!(vec!["text"], ft.mapping["*.Txt"]);
+    }
 }
@@ -8,23 +8,38 @@ use lazy_static::lazy_static;
 use yaml_rust::{yaml::Hash, Yaml};
 
 lazy_static! {
-    static ref ANSI_STYLES: HashMap<&'static str, u8> = {
+    static ref ANSI_STYLES: HashMap<&'static str, &'static str> = {
         let mut m = HashMap::new();
-        m.insert("regular", 0);
-        m.insert("bold", 1);
-        m.insert("faint", 2);
-        m.insert("italic", 3);
-        m.insert("underline", 4);
-        m.insert("blink", 5);
-        m.insert("rapid-blink", 6);
-        m.insert("overline", 53);
+        m.insert("regular", "0");
+        m.insert("bold", "1");
+        m.insert("faint", "2");
+        m.insert("italic", "3");
+        m.insert("underline", "4");
+        m.insert("blink", "5");
+        m.insert("rapid-blink", "6");
+        m.insert("reverse", "7");
+        m.insert("hidden", "8");
+        m.insert("strikethrough", "9");
+        m.insert("double-underline", "21");
+        m.insert("overline", "53");
+        // Extended underline styles (kitty, VTE, ...) use colon-separated subparameters
+        m.insert("curly-underline", "4:3");
+        m.insert("dotted-underline", "4:4");
+        m.insert("dashed-underline", "4:5");
         m
     };
 }
 
-/// A list of font styles
-#[derive(Default)]
-pub struct FontStyle(Vec<u8>);
+/// A list of font styles, stored as name and SGR parameters
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FontStyle(Vec<(&'static str, &'static str)>);
+
+fn lookup(name: &str) -> (&'static str, &'static str) {
+    ANSI_STYLES
+        .get_key_value(name)
+        .map(|(name, code)| (*name, *code))
+        .unwrap_or_else(|| panic!("unknown font-style '{}'", name))
+}
 
 impl FontStyle {
     /// Creates a FontStyle from the yaml
@@ -36,28 +51,52 @@ impl FontStyle {
     pub fn from_yaml(map: &Hash) -> Self {
         match map.get(&Yaml::String("font-style".into())) {
             Some(value) => match value {
-                Yaml::String(s) => Self(vec![ANSI_STYLES[s.as_str()]]),
+                Yaml::String(s) => Self(vec![lookup(s)]),
                 Yaml::Array(array) => {
                     let mut vec = Vec::with_capacity(array.len());
                     for item in array {
-                        vec.push(
-                            ANSI_STYLES[item
-                                .as_str()
-                                .expect("font_style should be a string or an array of strings")],
-                        );
+                        vec.push(lookup(
+                            item.as_str()
+                                .expect("font_style should be a string or an array of strings"),
+                        ));
                     }
                     Self(vec)
                 }
                 _ => panic!("font-style should be a string or an array of strings"),
             },
-            None => Self(vec![0]),
+            None => Self(vec![lookup("regular")]),
         }
     }
+
+    /// The SGR parameters including colon subparameters, for output that goes
+    /// directly to a terminal
+    ///
+    /// `LS_COLORS` uses colons to separate its entries, so the `Display`
+    /// implementation falls back to plain underlines instead.
+    pub fn extended_sgr(&self) -> String {
+        self.0
+            .iter()
+            .map(|(_, code)| *code)
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+
+    /// The names of the styles, as used in themes
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.0.iter().map(|(name, _)| *name)
+    }
+
+    /// The names of all known font styles
+    #[cfg(test)]
+    pub fn all_names() -> Vec<&'static str> {
+        ANSI_STYLES.keys().copied().collect()
+    }
 }
 
 impl Display for FontStyle {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        for (i, style) in self.0.iter().enumerate() {
+        for (i, (_, style)) in self.0.iter().enumerate() {
+            let style = style.split(':').next().unwrap_or(style);
             if i + 1 == self.0.len() {
                 write!(f, "{}", style)?;
             } else {
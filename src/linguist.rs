@@ -0,0 +1,227 @@
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::database::Import;
+use crate::error::{Result, VividError};
+use crate::filetypes::FileTypes;
+use crate::types::Category;
+
+/// A language from GitHub Linguist's `languages.yml`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Language {
+    pub name: String,
+    pub kind: String,
+    pub extensions: Vec<String>,
+    pub filenames: Vec<String>,
+}
+
+impl Language {
+    /// The category for the files of this language. This is the category
+    /// that already holds most of its extensions and file names in the
+    /// database. Languages that are not in the database yet get their own
+    /// `programming.source.<language>` category if they are programming
+    /// languages.
+    pub fn category(&self, filetypes: &FileTypes) -> Option<Category> {
+        let mut existing: Vec<(&Category, usize)> = Vec::new();
+        for entry in self.extensions.iter().chain(&self.filenames) {
+            if let Some(category) = filetypes.category_of(entry) {
+                match existing.iter_mut().find(|(c, _)| *c == category) {
+                    Some((_, count)) => *count += 1,
+                    None => existing.push((category, 1)),
+                }
+            }
+        }
+        // On a tie the category of the first entry wins
+        let most_common = existing
+            .iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .map(|(category, _)| (*category).clone());
+        if most_common.is_some() {
+            return most_common;
+        }
+
+        let category: &[&str] = match self.kind.as_str() {
+            "programming" => {
+                return Some(vec!["programming".into(), "source".into(), key(&self.name)])
+            }
+            "markup" => &["markup", "other"],
+            "data" | "prose" => &["text", "other"],
+            _ => return None,
+        };
+        Some(category.iter().map(|s| s.to_string()).collect())
+    }
+}
+
+/// Turn a language name into a category key like the ones in `filetypes.yml`,
+/// e.g. `C#` into `csharp` and `Common Lisp` into `common_lisp`
+fn key(name: &str) -> String {
+    let name = name
+        .to_lowercase()
+        .replace("++", "pp")
+        .replace('#', "sharp");
+    let mut key = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            key.push(c);
+        } else if !key.is_empty() && !key.ends_with('_') {
+            key.push('_');
+        }
+    }
+    key.trim_end_matches('_').to_string()
+}
+
+/// Parse Linguist's `languages.yml`
+pub fn parse_languages(contents: &str) -> Result<Vec<Language>> {
+    let docs = YamlLoader::load_from_str(contents)?;
+    let map = match docs.first() {
+        Some(Yaml::Hash(map)) => map,
+        _ => return Err(VividError::UnexpectedYamlType),
+    };
+
+    let strings = |value: &Yaml| -> Result<Vec<String>> {
+        match value {
+            Yaml::BadValue => Ok(vec![]),
+            Yaml::Array(array) => array
+                .iter()
+                .map(|item| {
+                    item.as_str()
+                        .map(String::from)
+                        .ok_or(VividError::UnexpectedYamlType)
+                })
+                .collect(),
+            _ => Err(VividError::UnexpectedYamlType),
+        }
+    };
+
+    let mut languages = map
+        .iter()
+        .map(|(name, language)| {
+            Ok(Language {
+                name: name
+                    .as_str()
+                    .ok_or(VividError::UnexpectedYamlType)?
+                    .to_string(),
+                kind: language["type"].as_str().unwrap_or_default().to_string(),
+                extensions: strings(&language["extensions"])?,
+                filenames: strings(&language["filenames"])?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    languages.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(languages)
+}
+
+/// Collect all extensions and file names of `languages` that the database
+/// does not know yet
+pub fn import(languages: &[Language], filetypes: &FileTypes) -> Import {
+    let mut result = Import::default();
+    for language in languages {
+        if let Some(category) = language.category(filetypes) {
+            for entry in language.extensions.iter().chain(&language.filenames) {
+                result.add(filetypes, &category, entry);
+            }
+        }
+    }
+    result.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import, key, parse_languages};
+    use crate::filetypes::FileTypes;
+
+    const LANGUAGES: &str = r##"
+Clojure:
+  type: programming
+  color: "#db5855"
+  extensions:
+  - ".clj"
+  - ".cljc"
+  - ".cljs"
+  filenames:
+  - riemann.config
+  language_id: 62
+C#:
+  type: programming
+  extensions:
+  - ".cs"
+  - ".csx"
+C++:
+  type: programming
+  extensions:
+  - ".cpp"
+  - ".hpp"
+JSON:
+  type: data
+  extensions:
+  - ".json"
+  - ".jsonl"
+Markdown:
+  type: markup
+  extensions:
+  - ".md"
+  - ".mdwn"
+Text:
+  type: prose
+  extensions:
+  - ".txt"
+  - ".fr"
+"##;
+
+    #[test]
+    fn category_keys() {
+        assert_eq!("csharp", key("C#"));
+        assert_eq!("cpp", key("C++"));
+        assert_eq!("common_lisp", key("Common Lisp"));
+        assert_eq!("ren_py", key("Ren'Py"));
+    }
+
+    #[test]
+    fn import_languages() {
+        let languages = parse_languages(LANGUAGES).unwrap();
+        assert_eq!(6, languages.len());
+        assert_eq!("C#", languages[0].name);
+        assert_eq!(vec!["riemann.config"], languages[2].filenames);
+
+        let filetypes = FileTypes::from_string(
+            "
+                programming:
+                  source:
+                    clojure: [.clj, .cljs, cljc]
+                    cxx: [.cpp]
+                text:
+                  configuration: [.json]
+                  other: [.txt]
+            ",
+        )
+        .unwrap();
+        let result = import(&languages, &filetypes);
+
+        let mut out = Vec::new();
+        result.write_changes(&mut out, &filetypes).unwrap();
+        assert_eq!(
+            "markup.other (new)
+  + .md
+  + .mdwn
+programming.source.clojure
+  + .cljc
+  + riemann.config
+programming.source.csharp (new)
+  + .cs
+  + .csx
+programming.source.cxx
+  + .hpp
+text.configuration
+  + .jsonl
+text.other
+  + .fr
+~ .cpp (already in programming.source.cxx)
+~ .clj (already in programming.source.clojure)
+~ .cljs (already in programming.source.clojure)
+~ .json (already in text.configuration)
+~ .txt (already in text.other)
+",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use crate::error::{Result, VividError};
+use crate::filetypes::FileTypes;
+use crate::theme::Theme;
+use crate::types::{Category, FileType};
+
+/// An ordered list of `LS_COLORS` entries (key and SGR style)
+#[derive(Debug, Clone, PartialEq)]
+pub struct LsColors {
+    entries: Vec<(String, String)>,
+}
+
+impl LsColors {
+    /// Resolve the style of every file type in the database
+    pub fn from_theme(filetypes: &FileTypes, theme: &Theme) -> Result<LsColors> {
+        Self::from_filetypes(filetypes.mapping.iter(), theme)
+    }
+
+    /// Resolve the style of every file name pattern, including glob patterns
+    /// that `LS_COLORS` can not express
+    ///
+    /// Core codes like `di` are left out: consumers with glob support (lf,
+    /// eza) read those from `LS_COLORS` anyway.
+    pub fn globs_from_theme(filetypes: &FileTypes, theme: &Theme) -> Result<LsColors> {
+        let patterns = filetypes
+            .mapping
+            .iter()
+            .filter(|(filetype, _)| filetype.starts_with('*'))
+            .chain(filetypes.globs.iter());
+        Self::from_filetypes(patterns, theme)
+    }
+
+    fn from_filetypes<'a>(
+        filetypes: impl Iterator<Item = (&'a FileType, &'a Category)>,
+        theme: &Theme,
+    ) -> Result<LsColors> {
+        let mut entries = filetypes
+            .map(|(filetype, category)| Ok((filetype.clone(), theme.get_style(category)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Sort the keys deterministically.  Shorter keys come first so that e.g.
+        // *README.md will override *.md.
+        entries.sort_unstable_by(|(a, _), (b, _)| (a.len(), a).cmp(&(b.len(), b)));
+
+        Ok(LsColors { entries })
+    }
+
+    /// Parse an existing `LS_COLORS` expression like `di=34:*.rs=0;31`
+    pub fn parse(ls_colors: &str) -> Result<LsColors> {
+        let mut entries = Vec::new();
+        for entry in ls_colors.trim().split(':').filter(|e| !e.is_empty()) {
+            match entry.split_once('=') {
+                Some((key, style)) if !key.is_empty() => {
+                    // Later entries take precedence, just like in `ls`
+                    entries.retain(|(k, _): &(String, String)| k != key);
+                    entries.push((key.to_string(), style.to_string()));
+                }
+                _ => return Err(VividError::InvalidLsColorsEntry(entry.to_string())),
+            }
+        }
+        Ok(LsColors { entries })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, style)| style.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, s)| (k.as_str(), s.as_str()))
+    }
+}
+
+impl std::fmt::Display for LsColors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (key, style)) in self.entries.iter().enumerate() {
+            if i > 0 {
+                write!(f, ":")?;
+            }
+            write!(f, "{}={}", key, style)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LsColors;
+    use crate::color::ColorMode;
+    use crate::filetypes::FileTypes;
+    use crate::theme::Theme;
+
+    #[test]
+    fn parse_roundtrip() {
+        let ls_colors = LsColors::parse("di=0;34:*.rs=0;31:*README.md=1\n").unwrap();
+        assert_eq!(Some("0;34"), ls_colors.get("di"));
+        assert_eq!(Some("1"), ls_colors.get("*README.md"));
+        assert_eq!(None, ls_colors.get("*.md"));
+        assert_eq!("di=0;34:*.rs=0;31:*README.md=1", ls_colors.to_string());
+    }
+
+    #[test]
+    fn parse_later_entries_win() {
+        let ls_colors = LsColors::parse("di=34:ln=36:di=1;34").unwrap();
+        assert_eq!(Some("1;34"), ls_colors.get("di"));
+        assert_eq!("ln=36:di=1;34", ls_colors.to_string());
+    }
+
+    #[test]
+    fn globs() {
+        let filetypes = FileTypes::from_string(
+            "
+                core:
+                  directory: [$di]
+                archives: ['*.tar.*', .tar.gz]
+            ",
+        )
+        .unwrap();
+        let theme = Theme::from_string(
+            "
+                colors: {}
+                core:
+                  font-style: bold
+                archives:
+                  font-style: underline
+            ",
+            ColorMode::BitDepth24,
+        )
+        .unwrap();
+
+        assert_eq!(
+            "di=1:*.tar.gz=4",
+            LsColors::from_theme(&filetypes, &theme)
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "*.tar.*=4:*.tar.gz=4",
+            LsColors::globs_from_theme(&filetypes, &theme)
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(LsColors::parse("di").is_err());
+        assert!(LsColors::parse("=34").is_err());
+    }
+}
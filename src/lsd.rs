@@ -0,0 +1,197 @@
+use std::io::Write;
+
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+use crate::color::{Color, ColorMode, ANSI_NAMES};
+use crate::document::to_yaml_string;
+use crate::error::{Result, VividError};
+use crate::palette::Palette;
+use crate::theme::Theme;
+
+/// The keys of lsd's `colors.yaml`, as styles in the theme's `lsd` section,
+/// with the palette slot that provides the color if the theme does not
+pub const SLOTS: [(&str, &str); 32] = [
+    ("user", "yellow"),
+    ("group", "bright_yellow"),
+    ("permission.read", "green"),
+    ("permission.write", "yellow"),
+    ("permission.exec", "red"),
+    ("permission.exec-sticky", "magenta"),
+    ("permission.no-access", "bright_black"),
+    ("permission.octal", "cyan"),
+    ("permission.acl", "cyan"),
+    ("permission.context", "bright_cyan"),
+    ("date.hour-old", "bright_green"),
+    ("date.day-old", "green"),
+    ("date.older", "cyan"),
+    ("size.none", "bright_black"),
+    ("size.small", "bright_yellow"),
+    ("size.medium", "yellow"),
+    ("size.large", "red"),
+    ("inode.valid", "magenta"),
+    ("inode.invalid", "bright_black"),
+    ("links.valid", "magenta"),
+    ("links.invalid", "bright_black"),
+    ("tree-edge", "bright_black"),
+    ("git-status.default", "bright_black"),
+    ("git-status.unmodified", "bright_black"),
+    ("git-status.ignored", "bright_black"),
+    ("git-status.new-in-index", "green"),
+    ("git-status.new-in-workdir", "green"),
+    ("git-status.typechange", "yellow"),
+    ("git-status.deleted", "red"),
+    ("git-status.renamed", "green"),
+    ("git-status.modified", "yellow"),
+    ("git-status.conflicted", "red"),
+];
+
+/// lsd's names of the 16 ANSI colors, in palette order
+const LSD_NAMES: [&str; 16] = [
+    "black",
+    "dark_red",
+    "dark_green",
+    "dark_yellow",
+    "dark_blue",
+    "dark_magenta",
+    "dark_cyan",
+    "grey",
+    "dark_grey",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+];
+
+/// A color as lsd reads it: a hex value in 24-bit mode, a palette index in
+/// 8-bit mode, and a name for colors of the ANSI palette
+fn color_value(color: Color, color_mode: ColorMode) -> Yaml {
+    match (color, color_mode) {
+        (Color::Ansi3Bit(_), _) => {
+            Yaml::String(LSD_NAMES[usize::from(color.ansi256_index())].into())
+        }
+        (Color::Rgb(..), ColorMode::BitDepth24) => Yaml::String(color.to_string()),
+        (Color::Rgb(..), ColorMode::BitDepth8) => Yaml::Integer(color.ansi256_index().into()),
+    }
+}
+
+/// The foreground color of a slot, or `None` if the theme does not style it
+fn foreground(theme: &Theme, slot: &str) -> Result<Option<Color>> {
+    let mut category = vec!["lsd".to_string()];
+    category.extend(slot.split('.').map(String::from));
+    match theme.resolve_style(&category) {
+        Ok(style) => Ok(style.foreground),
+        Err(VividError::CouldNotFindStyleFor(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Write a complete `colors.yaml` for lsd. Keys that the theme's `lsd` section
+/// does not style get the color of a palette slot, derived from the theme's
+/// RGB colors, or the ANSI color of that slot for themes without them.
+pub fn write(out: &mut dyn Write, theme_name: &str, theme: &Theme) -> Result<()> {
+    let palette = Palette::from_theme(theme).unwrap_or_default();
+
+    let mut colors = Hash::new();
+    for (slot, default) in SLOTS {
+        let color = match foreground(theme, slot)? {
+            Some(color) => color,
+            None => match palette.colors.get(default) {
+                Some((r, g, b)) => Color::Rgb(*r, *g, *b),
+                None => ANSI_NAMES
+                    .iter()
+                    .find(|(name, _)| *name == default)
+                    .map(|(_, color)| Color::Ansi3Bit(*color))
+                    .expect("default slots should be ANSI colors"),
+            },
+        };
+        let value = color_value(color, theme.color_mode());
+
+        let mut table = &mut colors;
+        let mut keys = slot.split('.').peekable();
+        while let Some(key) = keys.next() {
+            let key = Yaml::String(key.into());
+            if keys.peek().is_none() {
+                table.insert(key, value);
+                break;
+            }
+            let entry = table.entry(key).or_insert_with(|| Yaml::Hash(Hash::new()));
+            table = match entry {
+                Yaml::Hash(hash) => hash,
+                _ => unreachable!("lsd slots should not be nested in other slots"),
+            };
+        }
+    }
+
+    write!(
+        out,
+        "# lsd colors generated by vivid from the theme '{}'\n{}",
+        theme_name,
+        to_yaml_string(&Yaml::Hash(colors))?
+    )
+    .map_err(VividError::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write, SLOTS};
+    use crate::color::ColorMode;
+    use crate::document::DocumentFormat;
+    use crate::theme::Theme;
+
+    fn output(theme: &str, color_mode: ColorMode) -> String {
+        let theme = Theme::from_string(theme, color_mode).unwrap();
+        let mut out = Vec::new();
+        write(&mut out, "example", &theme).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn colors_yaml() {
+        let theme = "
+            colors:
+              background: '000000'
+              foreground: 'ffffff'
+              red: 'ff0000'
+              green: '00ff00'
+              blue: '0000ff'
+            lsd:
+              permission:
+                foreground: blue
+                exec:
+                  foreground: 'ansi:bright_red'
+              tree-edge:
+                font-style: bold
+        ";
+        let out = output(theme, ColorMode::BitDepth24);
+        assert!(out.starts_with("# lsd colors generated by vivid from the theme 'example'\n"));
+
+        let doc = DocumentFormat::Yaml.parse(&out).unwrap();
+        assert_eq!(Some("#0000ff"), doc["permission"]["read"].as_str());
+        assert_eq!(Some("red"), doc["permission"]["exec"].as_str());
+        assert_eq!(Some("#ff0000"), doc["size"]["large"].as_str());
+        assert_eq!(Some("#00ff00"), doc["git-status"]["new-in-index"].as_str());
+        // Styles without a foreground color fall back to the palette
+        assert!(doc["tree-edge"].as_str().is_some());
+        for (slot, _) in SLOTS {
+            let value = slot.split('.').fold(&doc, |doc, key| &doc[key]);
+            assert!(value.as_str().is_some(), "{}", slot);
+        }
+
+        let doc = DocumentFormat::Yaml
+            .parse(&output(theme, ColorMode::BitDepth8))
+            .unwrap();
+        assert_eq!(Some(21), doc["permission"]["read"].as_i64());
+        assert_eq!(Some(196), doc["size"]["large"].as_i64());
+
+        // Themes with only ANSI colors get lsd's color names
+        let doc = DocumentFormat::Yaml
+            .parse(&output("colors: {}", ColorMode::BitDepth24))
+            .unwrap();
+        assert_eq!(Some("dark_green"), doc["permission"]["read"].as_str());
+        assert_eq!(Some("dark_grey"), doc["tree-edge"].as_str());
+    }
+}
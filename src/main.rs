@@ -1,123 +1,170 @@
+mod base16;
 mod color;
+mod config;
+mod database;
+mod diff;
+mod document;
 mod error;
+mod export;
 mod filetypes;
 mod font_style;
+mod linguist;
+mod ls_colors;
+mod lsd;
+mod mc;
+mod mime;
+mod nvim;
+mod output;
+mod palette;
+mod picker;
+mod schema;
+mod terminal;
 mod theme;
+mod themes;
+mod tools;
 mod types;
 mod util;
+mod yazi;
 
 use etcetera::BaseStrategy;
-use rust_embed::RustEmbed;
+use std::env;
+use std::fmt::Display;
+use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::process;
-use std::{env, fs};
 
 use clap::{
     crate_description, crate_name, crate_version, Arg, ArgAction, ArgMatches, ColorChoice, Command,
 };
+use yaml_rust::Yaml;
 
+use crate::base16::Scheme;
 use crate::color::ColorMode;
+use crate::config::{CommandLine, ConfigDirs, ConfigFile, Settings, Source, ThemeChoice};
+use crate::document::DocumentFormat;
 use crate::error::{Result, VividError};
 use crate::filetypes::FileTypes;
-use crate::theme::Theme;
+use crate::ls_colors::LsColors;
+use crate::output::OutputFormat;
+use crate::palette::{Palette, PaletteFormat};
+use crate::schema::Schema;
+use crate::terminal::{Background, BackgroundSource, Multiplexer};
+use crate::theme::{Theme, Variant};
+use crate::themes::{available_theme_names, load_theme, load_theme_document};
+use crate::util::transpose;
 
-#[derive(RustEmbed)]
-#[folder = "themes/"]
-struct ThemeAssets;
-
-const THEME_PATH_SYSTEM: &str = "/usr/share/vivid/themes/";
-
-fn load_filetypes_database(matches: &ArgMatches, user_config_path: &Path) -> Result<FileTypes> {
-    let database_path_from_arg = matches.get_one::<String>("database").map(Path::new);
-
-    let mut database_path_user = user_config_path.to_owned();
-    database_path_user.push("filetypes.yml");
-
-    let database_path_env_s = env::var("VIVID_DATABASE").ok();
-    let database_path_env = database_path_env_s.as_ref().map(Path::new);
+/// The path of the filetypes database: the configured path, the first config directory
+/// that contains one or the system directory (in this order). `None` stands for the
+/// embedded defaults.
+fn filetypes_database_path(settings: &Settings, config_dirs: &ConfigDirs) -> Option<PathBuf> {
+    if let Some(database) = &settings.database {
+        return Some(database.value.clone());
+    }
 
-    let database_path_system = Path::new("/usr/share/vivid/filetypes.yml");
+    let database_paths: Vec<PathBuf> = config_dirs
+        .iter()
+        .map(|(_, dir)| dir)
+        .chain([Path::new("/usr/share/vivid")])
+        .flat_map(|dir| {
+            DocumentFormat::EXTENSIONS
+                .iter()
+                .map(move |extension| dir.join(format!("filetypes.{}", extension)))
+        })
+        .collect();
+    let database_paths: Vec<&Path> = database_paths.iter().map(|p| p.as_path()).collect();
 
-    let database_path = database_path_from_arg
-        .or(database_path_env)
-        .or_else(|| util::get_first_existing_path(&[&database_path_user, database_path_system]));
+    util::get_first_existing_path(&database_paths).map(Path::to_path_buf)
+}
 
-    // If there is a specified database file and it exists, use it.
-    // Otherwise, use the embedded file.
-    match database_path {
-        Some(path) => FileTypes::from_path(path),
+fn load_filetypes_database(settings: &Settings, config_dirs: &ConfigDirs) -> Result<FileTypes> {
+    match filetypes_database_path(settings, config_dirs) {
+        Some(path) => FileTypes::from_path(&path),
         None => FileTypes::from_embedded(),
     }
 }
 
-fn available_theme_names(user_config_path: &Path) -> Result<Vec<String>> {
-    let theme_path_user = user_config_path.join("themes");
-    let theme_path_system = PathBuf::from(THEME_PATH_SYSTEM);
-    let theme_paths = util::get_all_existing_paths(&[&theme_path_user, &theme_path_system]);
-
-    // build from default themes first
-    let mut available_themes: Vec<String> = ThemeAssets::iter()
-        .map(|theme_name| theme_name.trim_end_matches(".yml").to_owned())
-        .collect::<Vec<_>>();
-
-    for path in theme_paths {
-        let dir = fs::read_dir(path).map_err(VividError::IoError)?;
-        for theme_file in dir {
-            let theme_name = theme_file
-                .map_err(VividError::IoError)?
-                .file_name()
-                .into_string()
-                .map_err(|n| {
-                    VividError::InvalidFileName(n.as_os_str().to_string_lossy().into_owned())
-                })?;
-            available_themes.push(theme_name.trim_end_matches(".yml").to_owned());
+/// The format and contents of the filetypes database, see [`filetypes_database_path`]
+fn filetypes_database_source(
+    settings: &Settings,
+    config_dirs: &ConfigDirs,
+) -> Result<(DocumentFormat, String)> {
+    match filetypes_database_path(settings, config_dirs) {
+        Some(path) => {
+            let contents = util::load_yaml_file(&path)
+                .map_err(|_| VividError::CouldNotLoadDatabaseFrom(path.to_string_lossy().into()))?;
+            Ok((DocumentFormat::from_path(&path), contents))
         }
+        None => Ok((DocumentFormat::Yaml, FileTypes::embedded_contents()?)),
     }
-    available_themes.sort();
-    available_themes.dedup();
-    Ok(available_themes)
 }
 
-fn load_theme(
-    sub_matches: &ArgMatches,
-    user_config_path: &Path,
-    color_mode: ColorMode,
-) -> Result<Theme> {
-    let theme_from_env = env::var("VIVID_THEME").ok();
-    let theme = sub_matches
-        .get_one::<String>("theme")
-        .map(|s| s.as_str())
-        .or(theme_from_env.as_deref())
-        // Convert option to result, then unwrap value or return error if None
-        .ok_or_else(|| VividError::NoThemeProvided)?;
-
-    let theme_as_path = Path::new(theme);
-
-    let theme_file = format!("{}.yml", theme);
+/// Report the glob patterns of the database that an output format can not use
+fn warn_about_globs(filetypes: &FileTypes, format: OutputFormat) {
+    let mut globs = filetypes.globs.keys().collect::<Vec<_>>();
+    globs.sort_unstable();
+    for glob in globs {
+        eprintln!(
+            "Warning: '{}' is not supported by --format {}, it is only used with --format eza, lf or yazi",
+            glob,
+            format.name()
+        );
+    }
+}
 
-    let mut theme_path_user = user_config_path.to_owned();
-    theme_path_user.push("themes");
-    theme_path_user.push(theme_file.clone());
+/// The name of the theme to use, detecting the terminal background if the
+/// settings specify a light/dark pair
+fn configured_theme_name(settings: &Settings) -> Result<String> {
+    match &settings
+        .theme
+        .as_ref()
+        .ok_or(VividError::NoThemeProvided)?
+        .value
+    {
+        ThemeChoice::Fixed(theme) => Ok(theme.clone()),
+        ThemeChoice::Auto { light, dark } => {
+            let mut tty = terminal::controlling_tty();
+            let (background, _) =
+                terminal::detect_background(tty.as_deref_mut().map(|t| t as _), |var| {
+                    env::var(var).ok()
+                });
+            Ok(match background {
+                Background::Light => light.clone(),
+                Background::Dark => dark.clone(),
+            })
+        }
+    }
+}
 
-    let mut theme_path_system = PathBuf::new();
-    theme_path_system.push(THEME_PATH_SYSTEM);
-    theme_path_system.push(&theme_file);
+fn load_configured_theme(settings: &Settings, config_dirs: &ConfigDirs) -> Result<Theme> {
+    let theme = configured_theme_name(settings)?;
+    load_theme_for_terminal(&theme, settings, config_dirs)
+}
 
-    let theme_path =
-        util::get_first_existing_path(&[theme_as_path, &theme_path_user, &theme_path_system]);
+/// Load a theme in the configured color mode, with the terminal's palette if
+/// it was queried
+fn load_theme_for_terminal(
+    theme: &str,
+    settings: &Settings,
+    config_dirs: &ConfigDirs,
+) -> Result<Theme> {
+    let mut theme = load_theme(theme, config_dirs, settings.color_mode.value)?;
+    if let Some(palette) = terminal_palette(settings) {
+        theme.set_terminal_palette(palette);
+    }
+    Ok(theme)
+}
 
-    match theme_path {
-        Some(path) => return Theme::from_path(path, color_mode),
-        None => {
-            if let Some(embedded_file) = ThemeAssets::get(&theme_file) {
-                if let Ok(embedded_data) = std::str::from_utf8(&embedded_file.data) {
-                    return Theme::from_string(embedded_data, color_mode);
-                }
-            }
-        }
+/// The actual 16-color palette of the terminal, if `query-palette` is enabled
+/// and it makes a difference (only 8-bit colors are approximated)
+fn terminal_palette(settings: &Settings) -> Option<Palette> {
+    if !settings.query_palette.value || settings.color_mode.value != ColorMode::BitDepth8 {
+        return None;
     }
-    Err(VividError::CouldNotFindTheme(theme.to_string()))
+    let mut tty = terminal::controlling_tty()?;
+    terminal::query_palette(tty.as_mut())
+        .ok()
+        .filter(|palette| !palette.colors.is_empty())
 }
 
 fn cli() -> clap::Command {
@@ -135,8 +182,7 @@ fn cli() -> clap::Command {
                 .action(ArgAction::Set)
                 .value_name("mode")
                 .value_parser(["8-bit", "24-bit"])
-                .default_value("24-bit")
-                .help("Type of ANSI colors to be used"),
+                .help("Type of ANSI colors to be used [default: 24-bit]"),
         )
         .arg(
             Arg::new("database")
@@ -146,6 +192,28 @@ fn cli() -> clap::Command {
                 .value_name("path")
                 .help("Path to filetypes database (filetypes.yml)"),
         )
+        .arg(
+            Arg::new("query-palette")
+                .long("query-palette")
+                .action(ArgAction::SetTrue)
+                .help("Ask the terminal for its 16-color palette to approximate colors in 8-bit mode"),
+        )
+        .arg(
+            Arg::new("light-theme")
+                .long("light-theme")
+                .action(ArgAction::Set)
+                .value_name("theme")
+                .requires("dark-theme")
+                .help("Theme to use if the terminal has a light background"),
+        )
+        .arg(
+            Arg::new("dark-theme")
+                .long("dark-theme")
+                .action(ArgAction::Set)
+                .value_name("theme")
+                .requires("light-theme")
+                .help("Theme to use if the terminal has a dark background"),
+        )
         .subcommand(
             Command::new("generate")
                 .about("Generate a LS_COLORS expression")
@@ -153,6 +221,27 @@ fn cli() -> clap::Command {
                     Arg::new("theme")
                         .help("Name of the color theme")
                         .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .short('f')
+                        .action(ArgAction::Set)
+                        .value_name("format")
+                        .value_parser(OutputFormat::NAMES)
+                        .help("Output format [default: ls-colors]"),
+                )
+                .arg(
+                    Arg::new("case-insensitive")
+                        .long("case-insensitive")
+                        .action(ArgAction::SetTrue)
+                        .help("Also match all extensions in upper and title case (*.JPG, *.Jpg)"),
+                )
+                .arg(
+                    Arg::new("tools")
+                        .long("tools")
+                        .action(ArgAction::SetTrue)
+                        .help("Also export GREP_COLORS, GCC_COLORS, LESS_TERMCAP_* and JQ_COLORS for grep, GCC, man pages and jq (requires --format sh or fish)"),
                 ),
         )
         .subcommand(
@@ -162,52 +251,714 @@ fn cli() -> clap::Command {
                     .action(ArgAction::Set),
             ),
         )
-        .subcommand(Command::new("themes").about("Prints list of available themes"))
+        .subcommand(
+            Command::new("themes")
+                .about("Prints list of available themes")
+                .arg(
+                    Arg::new("light")
+                        .long("light")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("dark")
+                        .help("Only list themes for light backgrounds"),
+                )
+                .arg(
+                    Arg::new("dark")
+                        .long("dark")
+                        .action(ArgAction::SetTrue)
+                        .help("Only list themes for dark backgrounds"),
+                )
+                .arg(
+                    Arg::new("long")
+                        .long("long")
+                        .short('l')
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("json")
+                        .help("Show metadata and where each theme was loaded from"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Print the list of themes as JSON"),
+                ),
+        )
+        .subcommand(
+            Command::new("pick")
+                .about(
+                    "Interactively pick a theme with a live preview and print the shell line that uses it",
+                )
+                .arg(
+                    Arg::new("save")
+                        .long("save")
+                        .action(ArgAction::SetTrue)
+                        .help("Save the chosen theme to the vivid config file instead"),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Show which LS_COLORS entries differ between two themes, databases or LS_COLORS strings")
+                .arg(
+                    Arg::new("kind")
+                        .long("kind")
+                        .short('k')
+                        .action(ArgAction::Set)
+                        .value_name("kind")
+                        .value_parser(["theme", "database", "ls-colors"])
+                        .default_value("theme")
+                        .help("What the two arguments refer to"),
+                )
+                .arg(
+                    Arg::new("theme")
+                        .long("theme")
+                        .short('t')
+                        .action(ArgAction::Set)
+                        .value_name("theme")
+                        .help("Name of the color theme used to compare two databases"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Print the differences as JSON"),
+                )
+                .arg(
+                    Arg::new("old")
+                        .help("Old theme, database path or LS_COLORS string")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("new")
+                        .help("New theme, database path or LS_COLORS string")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("database")
+                .about("Maintain the filetypes database")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("import-mime")
+                        .about("Add file types from a shared-mime-info package to the database")
+                        .arg(
+                            Arg::new("path")
+                                .required(true)
+                                .value_name("path")
+                                .help("Path to the package, e.g. /usr/share/mime/packages/freedesktop.org.xml"),
+                        )
+                        .arg(
+                            Arg::new("rules")
+                                .long("rules")
+                                .action(ArgAction::Set)
+                                .value_name("path")
+                                .help("YAML file that maps MIME media types to categories"),
+                        )
+                        .arg(
+                            Arg::new("merge")
+                                .long("merge")
+                                .action(ArgAction::SetTrue)
+                                .help("Print the updated database instead of a list of changes"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("check")
+                        .about("Look for malformed entries in the database")
+                        .arg(
+                            Arg::new("all")
+                                .long("all")
+                                .action(ArgAction::SetTrue)
+                                .help("Also list suffixes that are overridden by longer ones from other categories"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("import-linguist")
+                        .about("Add extensions and file names from GitHub Linguist's languages.yml to the database")
+                        .arg(
+                            Arg::new("path")
+                                .required(true)
+                                .value_name("path")
+                                .help("Path to a local copy of languages.yml"),
+                        )
+                        .arg(
+                            Arg::new("merge")
+                                .long("merge")
+                                .action(ArgAction::SetTrue)
+                                .help("Print the updated database instead of a list of changes"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("convert")
+                .about("Translate a theme to YAML, TOML or JSON, without its comments")
+                .arg(
+                    Arg::new("theme")
+                        .required(true)
+                        .help("Name of or path to the color theme"),
+                )
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .short('t')
+                        .required(true)
+                        .action(ArgAction::Set)
+                        .value_name("format")
+                        .value_parser(DocumentFormat::NAMES)
+                        .help("Format of the converted theme"),
+                ),
+        )
+        .subcommand(
+            Command::new("base16")
+                .about("Turn a Base16 or Base24 color scheme into a theme")
+                .arg(
+                    Arg::new("scheme")
+                        .required(true)
+                        .value_name("path")
+                        .help("Path to the scheme, in the classic or the tinted-theming format"),
+                )
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .short('t')
+                        .action(ArgAction::Set)
+                        .value_name("format")
+                        .value_parser(DocumentFormat::NAMES)
+                        .default_value("yaml")
+                        .help("Output format"),
+                ),
+        )
+        .subcommand(
+            Command::new("palette")
+                .about("Exchange color palettes with terminal emulators")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("import")
+                        .about("Turn a terminal color scheme into a theme's colors block")
+                        .arg(
+                            Arg::new("path")
+                                .required(true)
+                                .value_name("path")
+                                .help("Color scheme of Alacritty, kitty, foot, WezTerm, X resources, iTerm2 or Windows Terminal"),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .short('f')
+                                .action(ArgAction::Set)
+                                .value_name("format")
+                                .value_parser(PaletteFormat::NAMES)
+                                .help("Format of the color scheme [default: detected from the file]"),
+                        )
+                        .arg(
+                            Arg::new("theme")
+                                .long("theme")
+                                .short('t')
+                                .action(ArgAction::Set)
+                                .value_name("theme")
+                                .help("Print a complete theme: this theme with its ansi: colors taken from the color scheme"),
+                        )
+                        .arg(
+                            Arg::new("to")
+                                .long("to")
+                                .action(ArgAction::Set)
+                                .value_name("format")
+                                .value_parser(DocumentFormat::NAMES)
+                                .default_value("yaml")
+                                .help("Output format"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("export")
+                        .about("Turn a theme's colors into a terminal color scheme")
+                        .arg(
+                            Arg::new("theme")
+                                .required(true)
+                                .help("Name of or path to the color theme"),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .short('f')
+                                .required(true)
+                                .action(ArgAction::Set)
+                                .value_name("format")
+                                .value_parser(PaletteFormat::NAMES)
+                                .help("Format of the color scheme"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("apply-palette")
+                .about("Set the colors of the running terminal to those of a theme")
+                .arg(
+                    Arg::new("theme")
+                        .help("Name of or path to the color theme")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("reset")
+                        .long("reset")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("theme")
+                        .help("Restore the terminal's own colors"),
+                ),
+        )
+        .subcommand(
+            Command::new("schema")
+                .about("Print the JSON schema of a file format, for use in editors")
+                .arg(
+                    Arg::new("name")
+                        .required(true)
+                        .value_parser(Schema::NAMES)
+                        .help("Theme, filetypes database or the JSON export of `vivid generate`"),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Inspect the vivid configuration")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("show")
+                        .about("Show the effective value of each setting and where it came from"),
+                ),
+        )
+}
+
+fn run_import_mime(
+    sub_matches: &ArgMatches,
+    settings: &Settings,
+    filetypes: &FileTypes,
+    config_dirs: &ConfigDirs,
+    stdout: &mut dyn Write,
+) -> Result<()> {
+    let path = Path::new(sub_matches.get_one::<String>("path").unwrap());
+    let xml = fs::read_to_string(path)
+        .map_err(|_| VividError::CouldNotReadMimePackage(path.to_string_lossy().into()))?;
+    let rules = match sub_matches.get_one::<String>("rules") {
+        Some(rules) => mime::parse_rules(&util::load_yaml_file(Path::new(rules))?)?,
+        None => mime::parse_rules(mime::DEFAULT_RULES)?,
+    };
+
+    let import = mime::import(&mime::parse_shared_mime_info(&xml)?, &rules, filetypes);
+    write_import(
+        &import,
+        sub_matches.get_flag("merge"),
+        filetypes,
+        settings,
+        config_dirs,
+        stdout,
+    )
+}
+
+fn run_import_linguist(
+    sub_matches: &ArgMatches,
+    settings: &Settings,
+    filetypes: &FileTypes,
+    config_dirs: &ConfigDirs,
+    stdout: &mut dyn Write,
+) -> Result<()> {
+    let path = Path::new(sub_matches.get_one::<String>("path").unwrap());
+    let languages = linguist::parse_languages(
+        &util::load_yaml_file(path)
+            .map_err(|_| VividError::CouldNotLoadDatabaseFrom(path.to_string_lossy().into()))?,
+    )?;
+
+    let import = linguist::import(&languages, filetypes);
+    write_import(
+        &import,
+        sub_matches.get_flag("merge"),
+        filetypes,
+        settings,
+        config_dirs,
+        stdout,
+    )
+}
+
+/// Print the changes of an import for review, or the current database with
+/// the new entries merged into it if `merge` is set
+fn write_import(
+    import: &database::Import,
+    merge: bool,
+    filetypes: &FileTypes,
+    settings: &Settings,
+    config_dirs: &ConfigDirs,
+    stdout: &mut dyn Write,
+) -> Result<()> {
+    for entry in &import.unsupported {
+        eprintln!("Skipped '{}', not a valid file type", entry);
+    }
+    if !merge {
+        import.write_changes(stdout, filetypes).ok();
+        return Ok(());
+    }
+
+    for (entry, category) in &import.skipped {
+        eprintln!("Skipped '{}', already in {}", entry, category.join("."));
+    }
+    let database = match filetypes_database_source(settings, config_dirs)? {
+        // Insert into the text, to keep the comments of the database
+        (DocumentFormat::Yaml, contents) => import.insert_into(&contents)?,
+        (format, contents) => {
+            let mut doc = format.parse(&contents)?;
+            import.apply(&mut doc)?;
+            DocumentFormat::Yaml.to_string(&doc)?
+        }
+    };
+    eprintln!(
+        "Added {} file types, skipped {}",
+        import.len(),
+        import.skipped.len() + import.unsupported.len()
+    );
+
+    write!(stdout, "{}", database).ok();
+    Ok(())
+}
+
+fn run_palette_import(
+    sub_matches: &ArgMatches,
+    config_dirs: &ConfigDirs,
+    stdout: &mut dyn Write,
+) -> Result<()> {
+    let path = Path::new(sub_matches.get_one::<String>("path").unwrap());
+    let contents = util::load_yaml_file(path)?;
+    let format = match sub_matches.get_one::<String>("format") {
+        Some(format) => format.parse()?,
+        None => PaletteFormat::detect(path, &contents).ok_or_else(|| {
+            VividError::InvalidPalette(format!(
+                "unknown format of '{}', use --format",
+                path.display()
+            ))
+        })?,
+    };
+    let palette = format.parse(path, &contents)?;
+
+    let doc = match sub_matches.get_one::<String>("theme") {
+        Some(theme) => {
+            let mut doc = load_theme_document(theme, config_dirs)?;
+            palette.apply_to_theme(&mut doc)?;
+            doc
+        }
+        None => {
+            let mut doc = yaml_rust::yaml::Hash::new();
+            doc.insert(
+                Yaml::String("colors".into()),
+                Yaml::Hash(palette.to_colors()),
+            );
+            Yaml::Hash(doc)
+        }
+    };
+
+    let to: DocumentFormat = sub_matches.get_one::<String>("to").unwrap().parse()?;
+    write!(stdout, "{}", to.to_string(&doc)?).ok();
+    Ok(())
+}
+
+fn run_diff(
+    sub_matches: &ArgMatches,
+    settings: &Settings,
+    filetypes: &FileTypes,
+    config_dirs: &ConfigDirs,
+    stdout: &mut dyn Write,
+) -> Result<()> {
+    let color_mode = settings.color_mode.value;
+    let old = sub_matches.get_one::<String>("old").unwrap();
+    let new = sub_matches.get_one::<String>("new").unwrap();
+
+    let mut databases: Vec<FileTypes> = vec![];
+    let (old_ls_colors, new_ls_colors) =
+        match sub_matches.get_one::<String>("kind").map(|s| s.as_str()) {
+            Some("database") => {
+                let theme = load_configured_theme(settings, config_dirs)?;
+                let old_filetypes = FileTypes::from_path(Path::new(old))?;
+                let new_filetypes = FileTypes::from_path(Path::new(new))?;
+                let ls_colors = (
+                    LsColors::from_theme(&old_filetypes, &theme)?,
+                    LsColors::from_theme(&new_filetypes, &theme)?,
+                );
+                databases.push(new_filetypes);
+                databases.push(old_filetypes);
+                ls_colors
+            }
+            Some("ls-colors") => (LsColors::parse(old)?, LsColors::parse(new)?),
+            _ => (
+                LsColors::from_theme(filetypes, &load_theme(old, config_dirs, color_mode)?)?,
+                LsColors::from_theme(filetypes, &load_theme(new, config_dirs, color_mode)?)?,
+            ),
+        };
+
+    // Keys are grouped by the category they have in the compared databases,
+    // falling back to the database that is currently in use.
+    let categorize = |key: &str| {
+        databases
+            .iter()
+            .chain(std::iter::once(filetypes))
+            .find_map(|db| db.mapping.get(key))
+            .map(|category| category.join("."))
+            .unwrap_or_else(|| "(unknown)".into())
+    };
+
+    let entries = diff::diff(&old_ls_colors, &new_ls_colors, categorize);
+    if sub_matches.get_flag("json") {
+        diff::write_json(stdout, &entries)?;
+    } else {
+        diff::write_text(stdout, &entries).ok();
+    }
+    Ok(())
+}
+
+fn run_themes(
+    sub_matches: &ArgMatches,
+    config_dirs: &ConfigDirs,
+    color_mode: ColorMode,
+    stdout: &mut dyn Write,
+) -> Result<()> {
+    let variant = if sub_matches.get_flag("light") {
+        Some(Variant::Light)
+    } else if sub_matches.get_flag("dark") {
+        Some(Variant::Dark)
+    } else {
+        None
+    };
+    let details = sub_matches.get_flag("long") || sub_matches.get_flag("json");
+
+    let entries = themes::available_themes(config_dirs)?;
+    if variant.is_none() && !details {
+        for entry in entries {
+            writeln!(stdout, "{}", entry.name).ok();
+        }
+        return Ok(());
+    }
+
+    let mut listing = vec![];
+    for entry in entries {
+        // One broken file should not hide all other themes
+        let theme = match themes::load_entry(&entry, color_mode) {
+            Ok(theme) => theme,
+            Err(e) => {
+                eprintln!("Warning: skipped the theme '{}': {}", entry.name, e);
+                continue;
+            }
+        };
+        if variant.is_none() || theme.meta.variant == variant {
+            listing.push((entry, theme));
+        }
+    }
+
+    if sub_matches.get_flag("json") {
+        themes::write_json(stdout, &listing)?;
+    } else if sub_matches.get_flag("long") {
+        themes::write_long(stdout, &listing).ok();
+    } else {
+        for (entry, _) in listing {
+            writeln!(stdout, "{}", entry.name).ok();
+        }
+    }
+    Ok(())
+}
+
+fn write_setting(
+    out: &mut dyn Write,
+    name: &str,
+    value: &str,
+    source: &dyn Display,
+) -> io::Result<()> {
+    writeln!(out, "{:<13} {:<24} ({})", name, value, source)
+}
+
+fn write_settings(out: &mut dyn Write, config_path: &Path, settings: &Settings) -> io::Result<()> {
+    writeln!(out, "config file: {}", config_path.display())?;
+    writeln!(out)?;
+
+    match &settings.theme {
+        Some(theme) => write_setting(out, "theme", &theme.value.to_string(), &theme.source)?,
+        None => write_setting(out, "theme", "(not set)", &Source::Default)?,
+    }
+    if let Some(ThemeChoice::Auto { .. }) = settings.theme.as_ref().map(|t| &t.value) {
+        let mut tty = terminal::controlling_tty();
+        let (background, source) =
+            terminal::detect_background(tty.as_deref_mut().map(|t| t as _), |var| {
+                env::var(var).ok()
+            });
+        let source = match source {
+            BackgroundSource::Query => "answer to OSC 11 query",
+            BackgroundSource::ColorFgBg => "environment variable COLORFGBG",
+            BackgroundSource::Environment => "environment variable VIVID_BACKGROUND",
+            BackgroundSource::Default => "built-in default",
+        };
+        write_setting(out, "background", background.name(), &source)?;
+    }
+    write_setting(
+        out,
+        "color-mode",
+        settings.color_mode.value.name(),
+        &settings.color_mode.source,
+    )?;
+    match &settings.database {
+        Some(database) => write_setting(
+            out,
+            "database",
+            &database.value.display().to_string(),
+            &database.source,
+        )?,
+        None => write_setting(out, "database", "(not set)", &Source::Default)?,
+    }
+    write_setting(
+        out,
+        "overlay-dirs",
+        &settings
+            .overlay_dirs
+            .value
+            .iter()
+            .map(|d| d.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+        &settings.overlay_dirs.source,
+    )?;
+    write_setting(
+        out,
+        "format",
+        settings.format.value.name(),
+        &settings.format.source,
+    )?;
+    write_setting(
+        out,
+        "query-palette",
+        &settings.query_palette.value.to_string(),
+        &settings.query_palette.source,
+    )
 }
 
 fn run() -> Result<()> {
     let matches = cli().get_matches();
-    let color_mode = match matches.get_one::<String>("color-mode").map(|s| s.as_str()) {
-        Some("8-bit") => ColorMode::BitDepth8,
-        _ => ColorMode::BitDepth24,
-    };
 
     let basedirs = etcetera::choose_base_strategy().expect("Could not get home directory");
     let user_config_path = basedirs.config_dir().join("vivid");
 
-    let filetypes = load_filetypes_database(&matches, &user_config_path)?;
+    let config_path = env::var_os("VIVID_CONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| user_config_path.join("config.yml"));
+    let config_file = if config_path.exists() {
+        Some(ConfigFile::from_path(&config_path)?)
+    } else {
+        None
+    };
+
+    // The theme is given as a positional argument to `generate` and `preview`,
+    // and as an option to `diff`
+    let sub_matches = matches.subcommand().map(|(_, sub_matches)| sub_matches);
+    let cli_values = CommandLine {
+        theme: sub_matches
+            .and_then(|m| m.try_get_one::<String>("theme").ok().flatten())
+            .map(|theme| ThemeChoice::Fixed(theme.clone()))
+            .or_else(|| {
+                match (
+                    matches.get_one::<String>("light-theme"),
+                    matches.get_one::<String>("dark-theme"),
+                ) {
+                    (Some(light), Some(dark)) => Some(ThemeChoice::Auto {
+                        light: light.clone(),
+                        dark: dark.clone(),
+                    }),
+                    _ => None,
+                }
+            }),
+        color_mode: transpose(
+            matches
+                .get_one::<String>("color-mode")
+                .map(|mode| mode.parse()),
+        )?,
+        database: matches.get_one::<String>("database").map(PathBuf::from),
+        format: transpose(
+            sub_matches
+                .and_then(|m| m.try_get_one::<String>("format").ok().flatten())
+                .map(|format| format.parse()),
+        )?,
+        query_palette: Some(true).filter(|_| matches.get_flag("query-palette")),
+    };
+    let term = env::var("TERM").ok();
+    let settings = Settings::resolve(
+        cli_values,
+        |var| env::var(var).ok(),
+        config_file.as_ref(),
+        term.as_deref(),
+    )?;
+    let color_mode = settings.color_mode.value;
+
+    let config_dirs = ConfigDirs {
+        overlays: settings.overlay_dirs.value.clone(),
+        user: user_config_path,
+    };
+
+    let mut filetypes = load_filetypes_database(&settings, &config_dirs)?;
+    // `vivid database check` reports the problems itself
+    let is_database_check = matches
+        .subcommand_matches("database")
+        .is_some_and(|m| m.subcommand_matches("check").is_some());
+    if !is_database_check {
+        filetypes.warn_about_issues();
+    }
 
     let stdout = io::stdout();
     let mut stdout_lock = stdout.lock();
 
     if let Some(sub_matches) = matches.subcommand_matches("generate") {
-        let theme = load_theme(sub_matches, &user_config_path, color_mode)?;
-
-        let mut mapping = filetypes
-            .mapping
-            .iter()
-            .map(|(filetype, category)| (filetype, theme.get_style(category)))
-            .map(|(filetype, style)| style.map(|style| (filetype, style)))
-            .collect::<Result<Vec<_>>>()?;
+        let theme_name = configured_theme_name(&settings)?;
+        let theme = load_theme_for_terminal(&theme_name, &settings, &config_dirs)?;
 
-        // Sort the keys deterministically.  Shorter keys come first so that e.g.
-        // *README.md will override *.md.
-        mapping.sort_unstable_by_key(|&(filetype, _)| (filetype.len(), filetype));
+        filetypes.add_case_variants(sub_matches.get_flag("case-insensitive"));
 
-        let ls_colors: Vec<_> = mapping
-            .iter()
-            .map(|(filetype, style)| format!("{}={}", filetype, style))
-            .collect();
+        let format = settings.format.value;
+        if sub_matches.get_flag("tools") && !format.supports_tools() {
+            return Err(VividError::ToolsNeedShellFormat(format.name().to_string()));
+        }
+        match format {
+            OutputFormat::Json | OutputFormat::Toml => {
+                let entries = export::resolve(&filetypes, &theme)?;
+                let write = if format == OutputFormat::Json {
+                    export::write_json
+                } else {
+                    export::write_toml
+                };
+                write(&mut stdout_lock, &theme_name, &theme, &entries)?;
+            }
+            OutputFormat::NvimLua => {
+                warn_about_globs(&filetypes, format);
+                nvim::write(&mut stdout_lock, &theme_name, &filetypes, &theme)?;
+            }
+            OutputFormat::Yazi => {
+                yazi::write(&mut stdout_lock, &theme_name, &filetypes, &theme)?;
+            }
+            OutputFormat::Lsd => {
+                lsd::write(&mut stdout_lock, &theme_name, &theme)?;
+            }
+            OutputFormat::Mc => {
+                warn_about_globs(&filetypes, format);
+                mc::write(&mut stdout_lock, &theme_name, &filetypes, &theme)?;
+            }
+            _ => {
+                let ls_colors = if format.supports_globs() {
+                    LsColors::globs_from_theme(&filetypes, &theme)?
+                } else {
+                    warn_about_globs(&filetypes, format);
+                    LsColors::from_theme(&filetypes, &theme)?
+                };
 
-        writeln!(stdout_lock, "{}", ls_colors.join(":")).ok();
-    } else if let Some(sub_matches) = matches.subcommand_matches("preview") {
-        let theme = load_theme(sub_matches, &user_config_path, color_mode)?;
+                if sub_matches.get_flag("tools") {
+                    let variables = tools::variables(&theme)?;
+                    output::write_variables(&mut stdout_lock, format, &ls_colors, &variables)?;
+                } else {
+                    output::write(&mut stdout_lock, format, &ls_colors)?;
+                }
+            }
+        }
+    } else if matches.subcommand_matches("preview").is_some() {
+        let theme = load_configured_theme(&settings, &config_dirs)?;
 
         let mut pairs = filetypes.mapping.iter().collect::<Vec<_>>();
         pairs.sort_by_key(|(_, category)| *category);
 
         for (entry, category) in pairs {
-            let ansi_code = theme.get_style(category).unwrap_or_else(|_| "0".into());
+            let ansi_code = theme
+                .get_terminal_style(category)
+                .unwrap_or_else(|_| "0".into());
             writeln!(
                 stdout_lock,
                 "{}: \x1b[{}m{}\x1b[0m",
@@ -217,9 +968,135 @@ fn run() -> Result<()> {
             )
             .ok();
         }
-    } else if matches.subcommand_matches("themes").is_some() {
-        for theme in available_theme_names(&user_config_path)? {
-            writeln!(stdout_lock, "{}", theme).ok();
+    } else if let Some(sub_matches) = matches.subcommand_matches("themes") {
+        run_themes(sub_matches, &config_dirs, color_mode, &mut stdout_lock)?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("pick") {
+        let picker = picker::Picker::new(
+            available_theme_names(&config_dirs)?,
+            &filetypes,
+            color_mode,
+            |theme, color_mode| load_theme(theme, &config_dirs, color_mode),
+        );
+        if let Some((theme, color_mode)) = picker::run(picker)? {
+            if sub_matches.get_flag("save") {
+                config::set_value(&config_path, "theme", &theme)?;
+                config::set_value(&config_path, "color-mode", color_mode.name())?;
+                eprintln!("Saved theme '{}' to {}", theme, config_path.display());
+
+                let config = ConfigFile::from_path(&config_path)?;
+                let term_layer = term.as_deref().and_then(|term| config.term_layer(term));
+                if let Some((pattern, layer)) = term_layer {
+                    if layer.theme.is_some() || layer.color_mode.is_some() {
+                        eprintln!(
+                            "Warning: the section 'terms.{}' overrides the saved setting for this terminal",
+                            pattern
+                        );
+                    }
+                }
+            } else {
+                let shell = env::var("SHELL").unwrap_or_default();
+                writeln!(
+                    stdout_lock,
+                    "{}",
+                    picker::shell_export_line(&shell, &theme, color_mode)
+                )
+                .ok();
+            }
+        }
+    } else if let Some(sub_matches) = matches.subcommand_matches("diff") {
+        run_diff(
+            sub_matches,
+            &settings,
+            &filetypes,
+            &config_dirs,
+            &mut stdout_lock,
+        )?;
+    } else if let Some(sub_matches) = matches.subcommand_matches("database") {
+        if let Some(sub_matches) = sub_matches.subcommand_matches("import-mime") {
+            run_import_mime(
+                sub_matches,
+                &settings,
+                &filetypes,
+                &config_dirs,
+                &mut stdout_lock,
+            )?;
+        } else if let Some(sub_matches) = sub_matches.subcommand_matches("check") {
+            let issues = database::check(&filetypes);
+            let mut warnings = 0;
+            for issue in &issues {
+                if !issue.is_note() {
+                    warnings += 1;
+                    writeln!(stdout_lock, "warning: {}", issue).ok();
+                } else if sub_matches.get_flag("all") {
+                    writeln!(stdout_lock, "note: {}", issue).ok();
+                }
+            }
+            if warnings > 0 {
+                return Err(VividError::DatabaseCheckFailed(warnings));
+            }
+        } else if let Some(sub_matches) = sub_matches.subcommand_matches("import-linguist") {
+            run_import_linguist(
+                sub_matches,
+                &settings,
+                &filetypes,
+                &config_dirs,
+                &mut stdout_lock,
+            )?;
+        }
+    } else if let Some(sub_matches) = matches.subcommand_matches("convert") {
+        let theme_name = sub_matches.get_one::<String>("theme").unwrap();
+        let format: DocumentFormat = sub_matches.get_one::<String>("to").unwrap().parse()?;
+
+        // Refuse to convert something that is not a theme
+        load_theme(theme_name, &config_dirs, color_mode)?;
+        let doc = load_theme_document(theme_name, &config_dirs)?;
+        write!(stdout_lock, "{}", format.to_string(&doc)?).ok();
+    } else if let Some(sub_matches) = matches.subcommand_matches("base16") {
+        let path = Path::new(sub_matches.get_one::<String>("scheme").unwrap());
+        let format: DocumentFormat = sub_matches.get_one::<String>("to").unwrap().parse()?;
+
+        let doc = Scheme::from_path(path)?.to_theme()?;
+        write!(stdout_lock, "{}", format.to_string(&doc)?).ok();
+    } else if let Some(sub_matches) = matches.subcommand_matches("palette") {
+        if let Some(sub_matches) = sub_matches.subcommand_matches("import") {
+            run_palette_import(sub_matches, &config_dirs, &mut stdout_lock)?;
+        } else if let Some(sub_matches) = sub_matches.subcommand_matches("export") {
+            let theme_name = sub_matches.get_one::<String>("theme").unwrap();
+            let format: PaletteFormat = sub_matches.get_one::<String>("format").unwrap().parse()?;
+
+            let theme = load_theme(theme_name, &config_dirs, color_mode)?;
+            let palette = Palette::from_theme(&theme)?;
+            write!(stdout_lock, "{}", format.export(&palette)?).ok();
+        }
+    } else if let Some(sub_matches) = matches.subcommand_matches("apply-palette") {
+        let sequences = if sub_matches.get_flag("reset") {
+            palette::RESET_SEQUENCES
+                .iter()
+                .map(|s| s.to_string())
+                .collect()
+        } else {
+            let theme_name = configured_theme_name(&settings)?;
+            let theme = load_theme_for_terminal(&theme_name, &settings, &config_dirs)?;
+            match Palette::from_theme(&theme) {
+                Ok(palette) => palette.osc_sequences(),
+                Err(VividError::NoRgbColors) => return Err(VividError::NothingToApply(theme_name)),
+                Err(e) => return Err(e),
+            }
+        };
+        let multiplexer = Multiplexer::detect(|var| env::var(var).ok());
+        for sequence in sequences {
+            match multiplexer {
+                Some(multiplexer) => write!(stdout_lock, "{}", multiplexer.wrap(&sequence)),
+                None => write!(stdout_lock, "{}", sequence),
+            }
+            .ok();
+        }
+    } else if let Some(sub_matches) = matches.subcommand_matches("schema") {
+        let schema: Schema = sub_matches.get_one::<String>("name").unwrap().parse()?;
+        write!(stdout_lock, "{}", schema.contents()).ok();
+    } else if let Some(sub_matches) = matches.subcommand_matches("config") {
+        if sub_matches.subcommand_matches("show").is_some() {
+            write_settings(&mut stdout_lock, &config_path, &settings).ok();
         }
     }
     Ok(())
@@ -229,6 +1106,8 @@ fn main() {
     let res = run();
     match res {
         Ok(()) => {}
+        // The output was piped into a program that exited early, like `head`
+        Err(VividError::IoError(e)) if e.kind() == io::ErrorKind::BrokenPipe => {}
         Err(e) => {
             eprintln!("Error: {}", e);
             process::exit(1);
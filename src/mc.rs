@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::color::Color;
+use crate::error::{Result, VividError};
+use crate::filetypes::FileTypes;
+use crate::theme::{Style, Theme};
+use crate::types::Category;
+
+/// Groups for the core file kinds: the group name, mc's file type and the
+/// vivid core category
+const CORE_GROUPS: [(&str, &str, &str); 10] = [
+    ("directory", "DIR", "directory"),
+    ("executable", "FILE_EXE", "executable_file"),
+    ("symlink", "SYMLINK", "symlink"),
+    ("stalelink", "STALE_LINK", "broken_symlink"),
+    ("hardlink", "HARDLINK", "multi_hard_link"),
+    ("blockdevice", "DEVICE_BLOCK", "block_device"),
+    ("chardevice", "DEVICE_CHAR", "character_device"),
+    ("socket", "SPECIAL_SOCKET", "socket"),
+    ("fifo", "SPECIAL_FIFO", "fifo"),
+    ("door", "SPECIAL_DOOR", "door"),
+];
+
+/// mc's names of the 16 ANSI colors, in palette order
+const MC_NAMES: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "brown",
+    "blue",
+    "magenta",
+    "cyan",
+    "lightgray",
+    "gray",
+    "brightred",
+    "brightgreen",
+    "yellow",
+    "brightblue",
+    "brightmagenta",
+    "brightcyan",
+    "white",
+];
+
+/// The skin attributes of vivid's font styles
+const ATTRIBUTES: [(&str, &str); 10] = [
+    ("bold", "bold"),
+    ("italic", "italic"),
+    ("underline", "underline"),
+    ("double-underline", "underline"),
+    ("curly-underline", "underline"),
+    ("dotted-underline", "underline"),
+    ("dashed-underline", "underline"),
+    ("reverse", "reverse"),
+    ("blink", "blink"),
+    ("rapid-blink", "blink"),
+];
+
+/// A color in mc's 256-color model: the name of an ANSI color or `colorN`
+fn color_value(color: Color) -> String {
+    match color {
+        Color::Ansi3Bit(_) => MC_NAMES[usize::from(color.ansi256_index())].into(),
+        Color::Rgb(..) => format!("color{}", color.ansi256_index()),
+    }
+}
+
+/// The `foreground;background;attributes` value of a skin entry
+fn skin_value(style: &Style) -> String {
+    let mut attributes = Vec::new();
+    for name in style.font_style.names() {
+        if let Some((_, attribute)) = ATTRIBUTES.iter().find(|(n, _)| *n == name) {
+            if !attributes.contains(attribute) {
+                attributes.push(*attribute);
+            }
+        }
+    }
+
+    let mut parts = vec![
+        style.foreground.map_or("default".into(), color_value),
+        style.background.map_or("default".into(), color_value),
+        attributes.join("+"),
+    ];
+    while parts.len() > 1
+        && (parts[parts.len() - 1].is_empty() || parts[parts.len() - 1] == "default")
+    {
+        parts.pop();
+    }
+    parts.join(";")
+}
+
+/// Escape a file name for a regular expression
+fn regex_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\.^$|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The group name of a top-level category, which must not clash with the
+/// groups of the core file kinds (like `executable`)
+fn group_name(category: &str) -> String {
+    if CORE_GROUPS.iter().any(|(name, _, _)| *name == category) {
+        format!("{}-files", category)
+    } else {
+        category.into()
+    }
+}
+
+/// The file names or extensions of one top-level category
+#[derive(Default)]
+struct Files {
+    keys: Vec<String>,
+    /// The number of files per theme node, to find the most common style
+    styles: BTreeMap<Category, (usize, Style)>,
+}
+
+impl Files {
+    fn push(&mut self, key: &str, style: Style) {
+        self.keys.push(key.into());
+        self.styles
+            .entry(style.node.clone())
+            .or_insert_with(|| (0, style))
+            .0 += 1;
+    }
+
+    /// The style of most files, the first theme node wins a tie
+    fn style(&self) -> &Style {
+        let mut best: Option<&(usize, Style)> = None;
+        for entry in self.styles.values() {
+            if best.is_none_or(|best| entry.0 > best.0) {
+                best = Some(entry);
+            }
+        }
+        &best.expect("groups should not be empty").1
+    }
+}
+
+/// Write a `filehighlight.ini` that groups the database by top-level category,
+/// followed by the `[filehighlight]` section of a skin with the colors of the
+/// groups. mc has one color per group, so each group gets the style that most
+/// of its files have. File names that are not extensions get a group of their
+/// own.
+pub fn write(
+    out: &mut dyn Write,
+    theme_name: &str,
+    filetypes: &FileTypes,
+    theme: &Theme,
+) -> Result<()> {
+    let mut names: BTreeMap<&str, Files> = BTreeMap::new();
+    let mut extensions: BTreeMap<&str, Files> = BTreeMap::new();
+    for (key, category) in &filetypes.mapping {
+        let suffix = match key.strip_prefix('*') {
+            Some(suffix) => suffix,
+            None => continue, // a core code like `di`
+        };
+        let style = theme.resolve_style(category)?;
+        match suffix.strip_prefix('.') {
+            Some(extension) => extensions
+                .entry(&category[0])
+                .or_default()
+                .push(extension, style),
+            None => names
+                .entry(&category[0])
+                .or_default()
+                .push(&regex_escape(suffix), style),
+        }
+    }
+
+    let mut ini = vec![
+        format!(
+            "# filehighlight.ini generated by vivid from the theme '{}'",
+            theme_name
+        ),
+        "# The [filehighlight] section at the end belongs into an mc skin".into(),
+    ];
+    let mut skin = Vec::new();
+    for (name, file_type, key) in CORE_GROUPS {
+        let style = theme.resolve_style(&["core".into(), key.into()])?;
+        ini.push(String::new());
+        ini.push(format!("[{}]", name));
+        ini.push(format!("    type={}", file_type));
+        skin.push((name.to_string(), skin_value(&style)));
+    }
+
+    // mc uses the first group that matches, so file names that end in an
+    // extension of another group (README.md) have to come first
+    for (category, files) in &mut names {
+        files.keys.sort_unstable();
+        let name = format!("{}-names", group_name(category));
+        ini.push(String::new());
+        ini.push(format!("[{}]", name));
+        ini.push(format!("    regexp=({})$", files.keys.join("|")));
+        skin.push((name, skin_value(files.style())));
+    }
+    for (category, files) in &mut extensions {
+        files.keys.sort_unstable();
+        let name = group_name(category);
+        ini.push(String::new());
+        ini.push(format!("[{}]", name));
+        ini.push(format!("    extensions={}", files.keys.join(";")));
+        skin.push((name, skin_value(files.style())));
+    }
+
+    ini.push(String::new());
+    if skin.iter().any(|(_, value)| value.contains("color")) {
+        ini.push("# Needs '256colors = true' in the [skin] section".into());
+    }
+    ini.push("[filehighlight]".into());
+    for (name, value) in skin {
+        ini.push(format!("    {}={}", name, value));
+    }
+
+    writeln!(out, "{}", ini.join("\n")).map_err(VividError::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write;
+    use crate::color::ColorMode;
+    use crate::filetypes::FileTypes;
+    use crate::theme::Theme;
+
+    #[test]
+    fn filehighlight() {
+        let filetypes = FileTypes::from_string(
+            "
+                core:
+                  directory: [$di]
+                  executable_file: [$ex]
+                  symlink: [$ln]
+                  broken_symlink: [$or]
+                  multi_hard_link: [$mh]
+                  block_device: [$bd]
+                  character_device: [$cd]
+                  socket: [$so]
+                  fifo: [$pi]
+                  door: [$do]
+                text:
+                  special: [README.md, CHANGELOG]
+                  other: [.txt, .md]
+                  todo: [TODO.txt]
+                archives: [.tar.gz, .zip, '*.tar.*']
+                executable: [.exe]
+            ",
+        )
+        .unwrap();
+        let theme = Theme::from_string(
+            "
+                colors:
+                  blue: '0000ff'
+                core:
+                  directory:
+                    foreground: blue
+                    font-style: bold
+                  executable_file:
+                    foreground: 'ansi:green'
+                  symlink: {}
+                  broken_symlink:
+                    foreground: 'ansi:black'
+                    background: 'ansi:bright_red'
+                  multi_hard_link: {}
+                  block_device: {}
+                  character_device: {}
+                  socket: {}
+                  fifo: {}
+                  door: {}
+                text:
+                  special:
+                    font-style: [underline, curly-underline]
+                  other:
+                    foreground: 'ansi:yellow'
+                  todo:
+                    foreground: 'ansi:yellow'
+                archives:
+                  foreground: 'ansi:magenta'
+                  font-style: [italic, rapid-blink]
+                executable:
+                  foreground: 'ansi:bright_green'
+            ",
+            ColorMode::BitDepth24,
+        )
+        .unwrap();
+
+        let mut out = Vec::new();
+        write(&mut out, "example", &filetypes, &theme).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.ends_with(
+            r"
+[text-names]
+    regexp=(CHANGELOG|README\.md|TODO\.txt)$
+
+[archives]
+    extensions=tar.gz;zip
+
+[executable-files]
+    extensions=exe
+
+[text]
+    extensions=md;txt
+
+# Needs '256colors = true' in the [skin] section
+[filehighlight]
+    directory=color21;default;bold
+    executable=green
+    symlink=default
+    stalelink=black;brightred
+    hardlink=default
+    blockdevice=default
+    chardevice=default
+    socket=default
+    fifo=default
+    door=default
+    text-names=default;default;underline
+    archives=magenta;default;italic+blink
+    executable-files=brightgreen
+    text=brown
+"
+        ));
+    }
+}
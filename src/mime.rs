@@ -0,0 +1,209 @@
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::database::Import;
+use crate::error::{Result, VividError};
+use crate::filetypes::FileTypes;
+use crate::types::Category;
+
+/// Which vivid category a MIME media type belongs to. The first matching rule wins.
+pub const DEFAULT_RULES: &str = "
+image/svg*: media.image.vector
+image/x-*-raw: media.image.raw
+image/*: media.image.bitmap
+audio/*: media.audio
+video/*: media.video
+application/x-*archive: archives.other
+application/x-*-compressed-tar: archives.other
+text/x-*src: programming.source.other
+";
+
+/// Maps MIME media types (with `*` wildcards) to a category
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub media_type: String,
+    pub category: Category,
+}
+
+/// Parse a rule table, a YAML mapping from media types to dotted category names
+pub fn parse_rules(contents: &str) -> Result<Vec<Rule>> {
+    let docs = YamlLoader::load_from_str(contents)?;
+    match docs.first() {
+        Some(Yaml::Hash(map)) => map
+            .iter()
+            .map(|(media_type, category)| match (media_type, category) {
+                (Yaml::String(media_type), Yaml::String(category)) => Ok(Rule {
+                    media_type: media_type.clone(),
+                    category: category.split('.').map(String::from).collect(),
+                }),
+                _ => Err(VividError::UnexpectedYamlType),
+            })
+            .collect(),
+        _ => Err(VividError::UnexpectedYamlType),
+    }
+}
+
+/// Match a media type against a pattern in which `*` stands for any sequence of characters
+fn matches(pattern: &str, media_type: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == media_type,
+        Some((prefix, rest)) => {
+            media_type.starts_with(prefix)
+                && (prefix.len()..=media_type.len())
+                    .any(|i| media_type.is_char_boundary(i) && matches(rest, &media_type[i..]))
+        }
+    }
+}
+
+/// A `<mime-type>` element of a shared-mime-info package
+#[derive(Debug, Clone, PartialEq)]
+pub struct MimeType {
+    pub media_type: String,
+    pub globs: Vec<String>,
+}
+
+/// Parse a shared-mime-info package like `/usr/share/mime/packages/freedesktop.org.xml`
+pub fn parse_shared_mime_info(xml: &str) -> Result<Vec<MimeType>> {
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..roxmltree::ParsingOptions::default()
+    };
+    let document = roxmltree::Document::parse_with_options(xml, options)
+        .map_err(|e| VividError::InvalidMimeInfo(e.to_string()))?;
+
+    Ok(document
+        .root_element()
+        .children()
+        .filter(|node| node.has_tag_name("mime-type"))
+        .filter_map(|node| {
+            Some(MimeType {
+                media_type: node.attribute("type")?.to_string(),
+                globs: node
+                    .children()
+                    .filter(|child| child.has_tag_name("glob"))
+                    .filter_map(|glob| glob.attribute("pattern"))
+                    .map(String::from)
+                    .collect(),
+            })
+        })
+        .collect())
+}
+
+/// Turn a shared-mime-info glob into a database entry: `*.png` becomes the
+/// suffix `.png`, everything else is kept as a file name or glob pattern
+fn to_entry(glob: &str) -> &str {
+    match glob.strip_prefix('*') {
+        Some(suffix) if !suffix.is_empty() && !suffix.contains(['*', '?', '[']) => suffix,
+        _ => glob,
+    }
+}
+
+/// Sort the globs of all MIME types that match a rule into categories,
+/// leaving out everything that `filetypes` already knows
+pub fn import(mime_types: &[MimeType], rules: &[Rule], filetypes: &FileTypes) -> Import {
+    let mut result = Import::default();
+
+    for mime_type in mime_types {
+        let rule = match rules
+            .iter()
+            .find(|rule| matches(&rule.media_type, &mime_type.media_type))
+        {
+            Some(rule) => rule,
+            None => continue,
+        };
+
+        for glob in &mime_type.globs {
+            result.add(filetypes, &rule.category, to_entry(glob));
+        }
+    }
+
+    result.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import, matches, parse_rules, parse_shared_mime_info, DEFAULT_RULES};
+    use crate::filetypes::FileTypes;
+
+    const MIME_INFO: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<mime-info xmlns="http://www.freedesktop.org/standards/shared-mime-info">
+  <mime-type type="image/png">
+    <comment>PNG image</comment>
+    <glob pattern="*.png"/>
+  </mime-type>
+  <mime-type type="image/svg+xml">
+    <glob pattern="*.svg"/>
+  </mime-type>
+  <mime-type type="application/x-cpio-compressed">
+    <glob pattern="*.cpio.gz"/>
+  </mime-type>
+  <mime-type type="application/x-tar-archive">
+    <glob pattern="*.tar"/>
+  </mime-type>
+  <mime-type type="text/x-csrc">
+    <glob pattern="*.c" case-sensitive="true"/>
+  </mime-type>
+  <mime-type type="text/x-makefile">
+    <glob pattern="Makefile.*"/>
+  </mime-type>
+  <mime-type type="audio/x-mod">
+    <glob pattern="*.669"/>
+    <glob pattern="mod.*"/>
+    <glob pattern="*.a b"/>
+  </mime-type>
+</mime-info>
+"#;
+
+    #[test]
+    fn media_type_patterns() {
+        assert!(matches("image/*", "image/png"));
+        assert!(matches(
+            "application/x-*archive",
+            "application/x-tar-archive"
+        ));
+        assert!(matches("text/x-*src", "text/x-c++src"));
+        assert!(!matches("text/x-*src", "text/x-csrc-header"));
+        assert!(!matches("image/png", "image/pngx"));
+    }
+
+    #[test]
+    fn import_mime_types() {
+        let mime_types = parse_shared_mime_info(MIME_INFO).unwrap();
+        assert_eq!(7, mime_types.len());
+        assert_eq!("image/png", mime_types[0].media_type);
+        assert_eq!(vec!["*.png"], mime_types[0].globs);
+
+        let filetypes = FileTypes::from_string("programming:\n  source:\n    c: [.c]\n").unwrap();
+        let rules = parse_rules(DEFAULT_RULES).unwrap();
+        let result = import(&mime_types, &rules, &filetypes);
+
+        let categories = result
+            .entries
+            .iter()
+            .map(|(category, entries)| (category.join("."), entries.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("archives.other".into(), vec![".tar".into()]),
+                ("media.audio".into(), vec![".669".into(), "mod.*".into()]),
+                ("media.image.bitmap".into(), vec![".png".into()]),
+                ("media.image.vector".into(), vec![".svg".into()]),
+            ] as Vec<(String, Vec<String>)>,
+            categories
+        );
+        assert_eq!(
+            vec![(".c", "programming.source.c".to_string())],
+            result
+                .skipped
+                .iter()
+                .map(|(entry, category)| (entry.as_str(), category.join(".")))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(vec![".a b"], result.unsupported);
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert!(parse_shared_mime_info("<mime-info>").is_err());
+        assert!(parse_rules("- image/*").is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::color::Color;
+use crate::error::{Result, VividError};
+use crate::filetypes::FileTypes;
+use crate::theme::{Style, Theme};
+use crate::types::CategoryRef;
+
+/// Highlight groups for the core file kinds that file explorers distinguish
+const CORE_GROUPS: [(&str, &str); 4] = [
+    ("VividDirectory", "directory"),
+    ("VividSymlink", "symlink"),
+    ("VividBrokenSymlink", "broken_symlink"),
+    ("VividExecutable", "executable_file"),
+];
+
+/// The `nvim_set_hl` attributes of vivid's font styles. Styles that Neovim
+/// does not know (faint, blink, hidden, overline) are left out.
+const ATTRIBUTES: [(&str, &str); 9] = [
+    ("bold", "bold"),
+    ("italic", "italic"),
+    ("underline", "underline"),
+    ("double-underline", "underdouble"),
+    ("curly-underline", "undercurl"),
+    ("dotted-underline", "underdotted"),
+    ("dashed-underline", "underdashed"),
+    ("strikethrough", "strikethrough"),
+    ("reverse", "reverse"),
+];
+
+/// Looks up the group of a file name: exact names first, then the extensions
+/// from the longest (`tar.gz`) to the shortest (`gz`) and finally the longest
+/// matching suffix (`~`)
+const LOOKUP_FUNCTION: &str = r#"--- The highlight group for a file name, or nil if vivid does not style it
+function M.group(name)
+  if M.filenames[name] then
+    return M.filenames[name]
+  end
+  local dot = name:find(".", 1, true)
+  while dot do
+    local group = M.extensions[name:sub(dot + 1)]
+    if group then
+      return group
+    end
+    dot = name:find(".", dot + 1, true)
+  end
+  local match, length = nil, 0
+  for suffix, group in pairs(M.suffixes) do
+    if #suffix > length and name:sub(-#suffix) == suffix then
+      match, length = group, #suffix
+    end
+  end
+  return match
+end
+"#;
+
+/// Whether a suffix without a dot is the name of a file, like `Makefile`,
+/// and not only the end of one, like `~`
+fn is_file_name(suffix: &str) -> bool {
+    suffix
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Quote a string for Lua, which has no `\u` escapes before 5.3
+fn lua_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\{}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The group name for a theme node, e.g. `VividProgrammingSource` for
+/// `programming.source`
+fn group_name(node: CategoryRef) -> String {
+    let mut name = String::from("Vivid");
+    for word in node.iter().flat_map(|key| key.split(['_', '-'])) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            name.extend(first.to_uppercase());
+            name.push_str(chars.as_str());
+        }
+    }
+    name
+}
+
+/// A GUI color: RGB colors as hex values, ANSI colors as the terminal color
+/// that Neovim was configured with (nil if there is none)
+fn gui_color(color: Color) -> String {
+    match color {
+        Color::Rgb(..) => lua_string(&color.to_string()),
+        Color::Ansi3Bit(_) => format!("vim.g.terminal_color_{}", color.ansi256_index()),
+    }
+}
+
+/// The attribute table of a `nvim_set_hl` call
+fn attributes(style: &Style) -> String {
+    let mut attributes = Vec::new();
+    if let Some(color) = style.foreground {
+        attributes.push(format!("fg = {}", gui_color(color)));
+        attributes.push(format!("ctermfg = {}", color.ansi256_index()));
+    }
+    if let Some(color) = style.background {
+        attributes.push(format!("bg = {}", gui_color(color)));
+        attributes.push(format!("ctermbg = {}", color.ansi256_index()));
+    }
+    if let Some(color) = style.underline_color {
+        attributes.push(format!("sp = {}", gui_color(color)));
+    }
+    for name in style.font_style.names() {
+        if let Some((_, attribute)) = ATTRIBUTES.iter().find(|(n, _)| *n == name) {
+            attributes.push(format!("{} = true", attribute));
+        }
+    }
+
+    if attributes.is_empty() {
+        "{}".into()
+    } else {
+        format!("{{ {} }}", attributes.join(", "))
+    }
+}
+
+/// Write a Lua module that defines a highlight group for each styled theme
+/// node and returns the tables that map extensions, file names and other
+/// suffixes to them. Glob patterns are left out, file explorers only look up
+/// names.
+pub fn write(
+    out: &mut dyn Write,
+    theme_name: &str,
+    filetypes: &FileTypes,
+    theme: &Theme,
+) -> Result<()> {
+    let mut groups = BTreeMap::new();
+    for (group, key) in CORE_GROUPS {
+        let style = theme.resolve_style(&["core".into(), key.into()])?;
+        groups.insert(group.to_string(), attributes(&style));
+    }
+
+    let mut extensions = BTreeMap::new();
+    let mut filenames = BTreeMap::new();
+    let mut suffixes = BTreeMap::new();
+    for (key, category) in &filetypes.mapping {
+        let suffix = match key.strip_prefix('*') {
+            Some(suffix) => suffix,
+            None => continue, // a core code like `di`
+        };
+        let style = theme.resolve_style(category)?;
+        let group = group_name(&style.node);
+        groups
+            .entry(group.clone())
+            .or_insert_with(|| attributes(&style));
+        match suffix.strip_prefix('.') {
+            Some(extension) => extensions.insert(extension, group),
+            None if is_file_name(suffix) => filenames.insert(suffix, group),
+            None => suffixes.insert(suffix, group),
+        };
+    }
+
+    let mut lines = vec![
+        format!("-- Highlight groups of the vivid theme '{}'", theme_name),
+        "local M = {}".into(),
+        String::new(),
+    ];
+    for (group, attributes) in &groups {
+        lines.push(format!(
+            "vim.api.nvim_set_hl(0, {}, {})",
+            lua_string(group),
+            attributes
+        ));
+    }
+    for (table, entries) in [
+        ("extensions", &extensions),
+        ("filenames", &filenames),
+        ("suffixes", &suffixes),
+    ] {
+        lines.push(String::new());
+        lines.push(format!("M.{} = {{", table));
+        for (name, group) in entries {
+            lines.push(format!("  [{}] = {},", lua_string(name), lua_string(group)));
+        }
+        lines.push("}".into());
+    }
+    lines.push(String::new());
+    lines.push(LOOKUP_FUNCTION.into());
+    lines.push("return M".into());
+
+    writeln!(out, "{}", lines.join("\n")).map_err(VividError::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{group_name, write};
+    use crate::color::ColorMode;
+    use crate::filetypes::FileTypes;
+    use crate::theme::Theme;
+
+    #[test]
+    fn highlight_groups() {
+        let filetypes = FileTypes::from_string(
+            "
+                core:
+                  directory: [$di]
+                  symlink: [$ln]
+                  broken_symlink: [$or]
+                  executable_file: [$ex]
+                programming:
+                  source:
+                    rust: [.rs, '*.tar.*']
+                  tooling: [Makefile, '~']
+            ",
+        )
+        .unwrap();
+        let theme = Theme::from_string(
+            "
+                colors:
+                  blue: '0000ff'
+                  red: 'ansi:red'
+                core:
+                  directory:
+                    foreground: blue
+                    font-style: [bold, faint]
+                  symlink:
+                    foreground: red
+                  broken_symlink:
+                    background: red
+                    font-style: curly-underline
+                    underline-color: blue
+                  executable_file: {}
+                programming:
+                  source:
+                    foreground: red
+                  tooling:
+                    font-style: italic
+            ",
+            ColorMode::BitDepth24,
+        )
+        .unwrap();
+
+        let mut out = Vec::new();
+        write(&mut out, "example", &filetypes, &theme).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(
+            r##"-- Highlight groups of the vivid theme 'example'
+local M = {}
+
+vim.api.nvim_set_hl(0, "VividBrokenSymlink", { bg = vim.g.terminal_color_1, ctermbg = 1, sp = "#0000ff", undercurl = true })
+vim.api.nvim_set_hl(0, "VividDirectory", { fg = "#0000ff", ctermfg = 21, bold = true })
+vim.api.nvim_set_hl(0, "VividExecutable", {})
+vim.api.nvim_set_hl(0, "VividProgrammingSource", { fg = vim.g.terminal_color_1, ctermfg = 1 })
+vim.api.nvim_set_hl(0, "VividProgrammingTooling", { italic = true })
+vim.api.nvim_set_hl(0, "VividSymlink", { fg = vim.g.terminal_color_1, ctermfg = 1 })
+
+M.extensions = {
+  ["rs"] = "VividProgrammingSource",
+}
+
+M.filenames = {
+  ["Makefile"] = "VividProgrammingTooling",
+}
+
+M.suffixes = {
+  ["~"] = "VividProgrammingTooling",
+}
+
+--- The highlight group for a file name"##
+        ));
+        assert!(out.ends_with("\nreturn M\n"));
+    }
+
+    #[test]
+    fn group_names() {
+        assert_eq!(
+            "VividCoreMultiHardLink",
+            group_name(&["core".into(), "multi_hard_link".into()])
+        );
+        assert_eq!(
+            "VividTextOtherData",
+            group_name(&["text".into(), "other-data".into()])
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use crate::error::{Result, VividError};
+use crate::ls_colors::LsColors;
+
+/// The output formats of `vivid generate`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    LsColors,
+    Sh,
+    Fish,
+    Eza,
+    Lf,
+    Json,
+    Toml,
+    NvimLua,
+    Yazi,
+    Lsd,
+    Mc,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 11] = [
+        "ls-colors",
+        "sh",
+        "fish",
+        "eza",
+        "lf",
+        "json",
+        "toml",
+        "nvim-lua",
+        "yazi",
+        "lsd",
+        "mc",
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::LsColors => "ls-colors",
+            OutputFormat::Sh => "sh",
+            OutputFormat::Fish => "fish",
+            OutputFormat::Eza => "eza",
+            OutputFormat::Lf => "lf",
+            OutputFormat::Json => "json",
+            OutputFormat::Toml => "toml",
+            OutputFormat::NvimLua => "nvim-lua",
+            OutputFormat::Yazi => "yazi",
+            OutputFormat::Lsd => "lsd",
+            OutputFormat::Mc => "mc",
+        }
+    }
+
+    /// Whether the variables of `generate --tools` can be written in this
+    /// format, which only shell statements can carry
+    pub fn supports_tools(self) -> bool {
+        matches!(self, OutputFormat::Sh | OutputFormat::Fish)
+    }
+
+    /// Whether the format is read by a program that understands glob patterns
+    /// (`EZA_COLORS`, `LF_COLORS`) instead of `LS_COLORS` suffixes
+    pub fn supports_globs(self) -> bool {
+        matches!(self, OutputFormat::Eza | OutputFormat::Lf)
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = VividError;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ls-colors" => Ok(OutputFormat::LsColors),
+            "sh" => Ok(OutputFormat::Sh),
+            "fish" => Ok(OutputFormat::Fish),
+            "eza" => Ok(OutputFormat::Eza),
+            "lf" => Ok(OutputFormat::Lf),
+            "json" => Ok(OutputFormat::Json),
+            "toml" => Ok(OutputFormat::Toml),
+            "nvim-lua" => Ok(OutputFormat::NvimLua),
+            "yazi" => Ok(OutputFormat::Yazi),
+            "lsd" => Ok(OutputFormat::Lsd),
+            "mc" => Ok(OutputFormat::Mc),
+            _ => Err(VividError::UnknownOutputFormat(s.to_string())),
+        }
+    }
+}
+
+fn single_quoted(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// A statement that exports an environment variable. Values with escape
+/// characters are passed through `printf` to keep them out of the output.
+fn assignment(format: OutputFormat, name: &str, value: &str) -> String {
+    let value = if value.contains('\x1b') {
+        let printf = format!("printf {}", single_quoted(&value.replace('\x1b', "\\033")));
+        match format {
+            OutputFormat::Fish => format!("({})", printf),
+            _ => format!("\"$({})\"", printf),
+        }
+    } else {
+        single_quoted(value)
+    };
+    match format {
+        OutputFormat::Fish => format!("set -gx {} {}", name, value),
+        _ => format!("export {}={}", name, value),
+    }
+}
+
+/// Write `LS_COLORS` and the variables of other tools as shell statements
+pub fn write_variables(
+    out: &mut dyn Write,
+    format: OutputFormat,
+    ls_colors: &LsColors,
+    variables: &[(&str, String)],
+) -> Result<()> {
+    if !format.supports_tools() {
+        return Err(VividError::ToolsNeedShellFormat(format.name().to_string()));
+    }
+    let ls_colors = ls_colors.to_string();
+    for (name, value) in std::iter::once(("LS_COLORS", ls_colors.as_str())).chain(
+        variables
+            .iter()
+            .map(|(name, value)| (*name, value.as_str())),
+    ) {
+        writeln!(out, "{}", assignment(format, name, value)).map_err(VividError::IoError)?;
+    }
+    Ok(())
+}
+
+/// Write an `LS_COLORS`-style expression. The JSON and TOML exports carry more
+/// information than `LsColors`, they are written by the `export` module. The
+/// Neovim highlight groups and the yazi, lsd and mc themes have their own
+/// modules, so these formats are an error here.
+pub fn write(out: &mut dyn Write, format: OutputFormat, ls_colors: &LsColors) -> Result<()> {
+    let ls_colors = ls_colors.to_string();
+    let line = match format {
+        OutputFormat::LsColors | OutputFormat::Eza | OutputFormat::Lf => ls_colors,
+        OutputFormat::Sh | OutputFormat::Fish => assignment(format, "LS_COLORS", &ls_colors),
+        OutputFormat::Json
+        | OutputFormat::Toml
+        | OutputFormat::NvimLua
+        | OutputFormat::Yazi
+        | OutputFormat::Lsd
+        | OutputFormat::Mc => {
+            return Err(VividError::NotAnLsColorsFormat(format.name().to_string()))
+        }
+    };
+    writeln!(out, "{}", line).map_err(VividError::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write, write_variables, OutputFormat};
+    use crate::ls_colors::LsColors;
+
+    fn output(format: OutputFormat) -> String {
+        let ls_colors = LsColors::parse("di=34:*it's=1").unwrap();
+        let mut out = Vec::new();
+        write(&mut out, format, &ls_colors).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn shell_formats() {
+        assert_eq!("di=34:*it's=1\n", output(OutputFormat::LsColors));
+        assert_eq!(
+            "export LS_COLORS='di=34:*it'\\''s=1'\n",
+            output(OutputFormat::Sh)
+        );
+        assert_eq!(
+            "set -gx LS_COLORS 'di=34:*it'\\''s=1'\n",
+            output(OutputFormat::Fish)
+        );
+
+        let ls_colors = LsColors::parse("di=34").unwrap();
+        assert!(write(&mut Vec::new(), OutputFormat::Json, &ls_colors).is_err());
+    }
+
+    #[test]
+    fn tool_variables() {
+        let ls_colors = LsColors::parse("di=34").unwrap();
+        let variables = [
+            ("GREP_COLORS", "ms=1;31".to_string()),
+            ("LESS_TERMCAP_md", "\x1b[1m".to_string()),
+        ];
+        let output = |format| {
+            let mut out = Vec::new();
+            write_variables(&mut out, format, &ls_colors, &variables).map(|_| out)
+        };
+
+        assert_eq!(
+            "export LS_COLORS='di=34'\nexport GREP_COLORS='ms=1;31'\n\
+             export LESS_TERMCAP_md=\"$(printf '\\033[1m')\"\n",
+            String::from_utf8(output(OutputFormat::Sh).unwrap()).unwrap()
+        );
+        assert_eq!(
+            "set -gx LS_COLORS 'di=34'\nset -gx GREP_COLORS 'ms=1;31'\n\
+             set -gx LESS_TERMCAP_md (printf '\\033[1m')\n",
+            String::from_utf8(output(OutputFormat::Fish).unwrap()).unwrap()
+        );
+        assert!(output(OutputFormat::LsColors).is_err());
+        assert!(output(OutputFormat::Json).is_err());
+    }
+}
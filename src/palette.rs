@@ -0,0 +1,1177 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+use crate::color::{Color, ANSI_NAMES};
+use crate::document::DocumentFormat;
+use crate::error::{Result, VividError};
+use crate::terminal::{parse_x11_color, Background};
+use crate::theme::{Theme, Variant};
+
+pub type Rgb = (u8, u8, u8);
+
+/// The normalized names of the colors of a terminal color scheme: the 16 ANSI
+/// colors (as in `ansi:` theme colors), then the default colors
+pub const SLOTS: [&str; 19] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright_black",
+    "bright_red",
+    "bright_green",
+    "bright_yellow",
+    "bright_blue",
+    "bright_magenta",
+    "bright_cyan",
+    "bright_white",
+    "foreground",
+    "background",
+    "cursor",
+];
+
+/// The colors of a terminal color scheme
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Palette {
+    pub name: Option<String>,
+    pub colors: HashMap<&'static str, Rgb>,
+}
+
+impl Palette {
+    fn set(&mut self, slot: &'static str, color: Option<Rgb>) {
+        if let Some(color) = color {
+            self.colors.insert(slot, color);
+        }
+    }
+
+    /// Set the n-th ANSI color
+    fn set_ansi(&mut self, index: usize, color: Option<Rgb>) {
+        if let Some((slot, _)) = ANSI_NAMES.get(index) {
+            self.set(slot, color);
+        }
+    }
+
+    pub fn background(&self) -> Option<Background> {
+        self.colors
+            .get("background")
+            .map(|&color| Background::from_rgb(color))
+    }
+
+    /// The palette as a theme `colors:` map, in the order of [`SLOTS`]
+    pub fn to_colors(&self) -> Hash {
+        SLOTS
+            .iter()
+            .filter_map(|slot| {
+                let color = self.colors.get(slot)?;
+                Some((Yaml::String(slot.to_string()), Yaml::String(hex(*color))))
+            })
+            .collect()
+    }
+
+    /// Replace the `ansi:` colors in a theme node and all nodes below it,
+    /// returning whether some of them are not part of this palette
+    fn resolve_ansi_colors(&self, node: &mut Yaml) -> bool {
+        match node {
+            Yaml::String(value) => match value.strip_prefix("ansi:") {
+                Some(name) => match self.colors.get(name) {
+                    Some(color) => {
+                        *node = Yaml::String(hex(*color));
+                        false
+                    }
+                    None => true,
+                },
+                None => false,
+            },
+            Yaml::Hash(map) => map.iter_mut().fold(false, |left, (_, value)| {
+                self.resolve_ansi_colors(value) || left
+            }),
+            _ => false,
+        }
+    }
+
+    /// Turn a theme into one with concrete RGB values: `ansi:` colors, in the
+    /// `colors:` map as well as in the styles, are replaced by the colors of
+    /// this palette, and the metadata is updated. If the palette does not
+    /// have all of the theme's ANSI colors, the theme keeps its
+    /// `min-color-depth`.
+    pub fn apply_to_theme(&self, doc: &mut Yaml) -> Result<()> {
+        let map = match doc {
+            Yaml::Hash(map) => map,
+            _ => return Err(VividError::UnexpectedYamlType),
+        };
+
+        let meta_key = Yaml::String("meta".into());
+        let mut ansi_left = false;
+        for (key, value) in map.iter_mut() {
+            if *key != meta_key {
+                ansi_left = self.resolve_ansi_colors(value) || ansi_left;
+            }
+        }
+
+        let mut meta = match map.remove(&meta_key) {
+            Some(Yaml::Hash(meta)) => meta,
+            _ => Hash::new(),
+        };
+        if !ansi_left {
+            // The theme no longer needs the terminal's palette
+            meta.remove(&Yaml::String("min-color-depth".into()));
+        }
+        if let Some(name) = &self.name {
+            meta.insert(Yaml::String("name".into()), Yaml::String(name.clone()));
+        }
+        if let Some(background) = self.background() {
+            meta.insert(
+                Yaml::String("variant".into()),
+                Yaml::String(background.name().into()),
+            );
+        }
+
+        let mut theme = Hash::new();
+        theme.insert(meta_key, Yaml::Hash(meta));
+        theme.extend(map.iter().map(|(k, v)| (k.clone(), v.clone())));
+        *map = theme;
+        Ok(())
+    }
+
+    /// The escape sequences that make this the palette of the running
+    /// terminal: OSC 4 for the ANSI colors, OSC 10, 11 and 12 for the default
+    /// foreground, background and cursor color
+    pub fn osc_sequences(&self) -> Vec<String> {
+        let x11 = |(r, g, b): Rgb| format!("rgb:{:02x}/{:02x}/{:02x}", r, g, b);
+        let mut sequences: Vec<String> = ANSI_NAMES
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (name, _))| {
+                let color = self.colors.get(name)?;
+                Some(format!("\x1b]4;{};{}\x07", i, x11(*color)))
+            })
+            .collect();
+        for (code, slot) in [(10, "foreground"), (11, "background"), (12, "cursor")] {
+            if let Some(color) = self.colors.get(slot) {
+                sequences.push(format!("\x1b]{};{}\x07", code, x11(*color)));
+            }
+        }
+        sequences
+    }
+
+    /// Derive a terminal color scheme from the RGB colors of a theme. Colors
+    /// named like a slot (`red`, `bright_blue`, `background`, or `fg`, `bg`,
+    /// `purple`, `light_red`, `gray`) are used for that slot. The other slots
+    /// get the neutral color of the closest lightness, or the color of the
+    /// closest hue.
+    pub fn from_theme(theme: &Theme) -> Result<Palette> {
+        let mut colors: Vec<(&str, Rgb)> = theme
+            .colors()
+            .iter()
+            .filter_map(|(name, color)| match color {
+                Color::Rgb(r, g, b) => Some((name.as_str(), (*r, *g, *b))),
+                Color::Ansi3Bit(_) => None,
+            })
+            .collect();
+        if colors.is_empty() {
+            return Err(VividError::NoRgbColors);
+        }
+        // The colors of a theme are not ordered, sort them to get the same
+        // result every time
+        colors.sort_unstable();
+
+        let mut palette = Palette {
+            name: theme.meta.name.clone(),
+            ..Palette::default()
+        };
+        for (name, color) in &colors {
+            if let Some(slot) = SLOTS.iter().find(|slot| *slot == name) {
+                palette.colors.insert(slot, *color);
+            }
+        }
+        for (name, color) in &colors {
+            if let Some(slot) = role_hint(name) {
+                palette.colors.entry(slot).or_insert(*color);
+            }
+        }
+
+        let (chromatic, mut neutrals): (Vec<Rgb>, Vec<Rgb>) = colors
+            .iter()
+            .map(|(_, color)| *color)
+            .partition(|color| is_chromatic(*color));
+        if neutrals.is_empty() {
+            neutrals = colors.iter().map(|(_, color)| *color).collect();
+        }
+        neutrals.sort_by(|a, b| hsl(*a).2.total_cmp(&hsl(*b).2));
+        let darkest = neutrals[0];
+        let lightest = neutrals[neutrals.len() - 1];
+        let closest_lightness = |lightness: f64| {
+            neutrals
+                .iter()
+                .copied()
+                .min_by(|a, b| {
+                    let distance = |c: &Rgb| (hsl(*c).2 - lightness).abs();
+                    distance(a).total_cmp(&distance(b))
+                })
+                .unwrap_or(darkest)
+        };
+
+        let (background, foreground) = match theme.meta.variant {
+            Some(Variant::Light) => (lightest, darkest),
+            _ => (darkest, lightest),
+        };
+        let mut fill =
+            |slot: &'static str, color: Rgb| *palette.colors.entry(slot).or_insert(color);
+        fill("background", background);
+        fill("foreground", foreground);
+        fill("black", darkest);
+        fill("bright_black", closest_lightness(0.45));
+        fill("white", closest_lightness(0.75));
+        fill("bright_white", lightest);
+
+        for (slot, bright_slot, hue) in HUES {
+            let mut candidates = chromatic.clone();
+            candidates.sort_by(|a, b| {
+                let (hue_a, saturation_a, _) = hsl(*a);
+                let (hue_b, saturation_b, _) = hsl(*b);
+                hue_distance(hue_a, hue)
+                    .total_cmp(&hue_distance(hue_b, hue))
+                    .then(saturation_b.total_cmp(&saturation_a))
+            });
+            candidates.dedup();
+            let closest = candidates.first().copied().unwrap_or(foreground);
+            // A second color of about the same hue is used for the bright
+            // variant if it is the lighter one
+            let other = candidates
+                .iter()
+                .skip(1)
+                .find(|c| hue_distance(hsl(**c).0, hsl(closest).0) <= 15.0)
+                .copied()
+                .unwrap_or(closest);
+            let (darker, lighter) = if hsl(other).2 < hsl(closest).2 {
+                (other, closest)
+            } else {
+                (closest, other)
+            };
+            let normal = fill(slot, darker);
+            fill(bright_slot, if normal == darker { lighter } else { normal });
+        }
+        Ok(palette)
+    }
+}
+
+/// The escape sequences that restore the terminal's own palette (OSC 104) and
+/// default foreground, background and cursor colors (OSC 110, 111 and 112)
+pub const RESET_SEQUENCES: [&str; 4] = [
+    "\x1b]104\x07",
+    "\x1b]110\x07",
+    "\x1b]111\x07",
+    "\x1b]112\x07",
+];
+
+/// Whether a color is saturated and neither too dark nor too light to be
+/// assigned by hue. The others are candidates for black and white.
+fn is_chromatic(color: Rgb) -> bool {
+    let (_, saturation, lightness) = hsl(color);
+    saturation >= 0.25 && (0.3..=0.85).contains(&lightness)
+}
+
+/// The ANSI colors that are assigned by hue, and their hue in degrees
+const HUES: [(&str, &str, f64); 6] = [
+    ("red", "bright_red", 0.0),
+    ("yellow", "bright_yellow", 60.0),
+    ("green", "bright_green", 120.0),
+    ("cyan", "bright_cyan", 180.0),
+    ("blue", "bright_blue", 240.0),
+    ("magenta", "bright_magenta", 300.0),
+];
+
+/// The slot that a theme color name other than a slot name hints at
+fn role_hint(name: &str) -> Option<&'static str> {
+    let slot = match name {
+        "fg" => "foreground",
+        "bg" => "background",
+        "gray" | "grey" => "bright_black",
+        "purple" => "magenta",
+        "light_purple" | "bright_purple" => "bright_magenta",
+        _ => {
+            let bright = format!("bright_{}", name.strip_prefix("light_")?);
+            return SLOTS.iter().find(|slot| **slot == bright).copied();
+        }
+    };
+    Some(slot)
+}
+
+/// Hue in degrees, saturation and lightness (from 0 to 1) of a color
+fn hsl((r, g, b): Rgb) -> (f64, f64, f64) {
+    let (r, g, b) = (
+        f64::from(r) / 255.0,
+        f64::from(g) / 255.0,
+        f64::from(b) / 255.0,
+    );
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0.0, 0.0, lightness);
+    }
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (hue, saturation, lightness)
+}
+
+fn hue_distance(a: f64, b: f64) -> f64 {
+    let distance = (a - b).abs() % 360.0;
+    distance.min(360.0 - distance)
+}
+
+fn hex((r, g, b): Rgb) -> String {
+    format!("{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Parse `#rrggbb`, `0xrrggbb`, `rrggbb`, `#rgb` or an X11 `rgb:r/g/b` color
+pub fn parse_color(s: &str) -> Option<Rgb> {
+    let s = s.trim().trim_matches(|c| c == '"' || c == '\'');
+    if s.starts_with("rgb:") {
+        return parse_x11_color(s);
+    }
+    let digits = s
+        .strip_prefix('#')
+        .or_else(|| s.strip_prefix("0x"))
+        .unwrap_or(s);
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let component = |i: usize, len: usize| u8::from_str_radix(&digits[i * len..(i + 1) * len], 16);
+    match digits.len() {
+        6 => Some((
+            component(0, 2).ok()?,
+            component(1, 2).ok()?,
+            component(2, 2).ok()?,
+        )),
+        3 => {
+            let (r, g, b) = (
+                component(0, 1).ok()?,
+                component(1, 1).ok()?,
+                component(2, 1).ok()?,
+            );
+            Some((r * 17, g * 17, b * 17))
+        }
+        _ => None,
+    }
+}
+
+/// The terminal emulator color scheme formats that can be imported and exported
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaletteFormat {
+    Alacritty,
+    Kitty,
+    Foot,
+    Wezterm,
+    Xresources,
+    Iterm,
+    WindowsTerminal,
+}
+
+impl PaletteFormat {
+    pub const NAMES: [&'static str; 7] = [
+        "alacritty",
+        "kitty",
+        "foot",
+        "wezterm",
+        "xresources",
+        "iterm",
+        "windows-terminal",
+    ];
+
+    /// Guess the format of a color scheme file from its name and contents
+    pub fn detect(path: &Path, contents: &str) -> Option<PaletteFormat> {
+        let file_name = path.file_name()?.to_str()?.to_lowercase();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        match extension {
+            "itermcolors" => Some(PaletteFormat::Iterm),
+            "json" => Some(PaletteFormat::WindowsTerminal),
+            "yml" | "yaml" => Some(PaletteFormat::Alacritty),
+            // Both use TOML, but only WezTerm has `ansi` arrays
+            "toml" if contents.contains("ansi") && contents.contains("brights") => {
+                Some(PaletteFormat::Wezterm)
+            }
+            "toml" => Some(PaletteFormat::Alacritty),
+            "conf" => Some(PaletteFormat::Kitty),
+            "ini" => Some(PaletteFormat::Foot),
+            _ if file_name.contains("xresources") || file_name.contains("xdefaults") => {
+                Some(PaletteFormat::Xresources)
+            }
+            _ if contents.contains("regular0") => Some(PaletteFormat::Foot),
+            _ if contents
+                .lines()
+                .any(|l| l.trim_start().starts_with("color0 ")) =>
+            {
+                Some(PaletteFormat::Kitty)
+            }
+            _ if contents.contains("color0:") => Some(PaletteFormat::Xresources),
+            _ => None,
+        }
+    }
+
+    pub fn parse(self, path: &Path, contents: &str) -> Result<Palette> {
+        let mut palette = match self {
+            PaletteFormat::Alacritty => {
+                alacritty(&DocumentFormat::from_path(path).parse(contents)?)
+            }
+            PaletteFormat::Kitty => kitty(contents),
+            PaletteFormat::Foot => foot(contents),
+            PaletteFormat::Wezterm => wezterm(&DocumentFormat::Toml.parse(contents)?),
+            PaletteFormat::Xresources => xresources(contents),
+            PaletteFormat::Iterm => iterm(contents)?,
+            PaletteFormat::WindowsTerminal => {
+                windows_terminal(&DocumentFormat::Json.parse(contents)?)
+            }
+        };
+        if palette.colors.is_empty() {
+            return Err(VividError::InvalidPalette(format!(
+                "no colors found in '{}'",
+                path.display()
+            )));
+        }
+        if palette.name.is_none() {
+            palette.name = path.file_stem().map(|s| s.to_string_lossy().into_owned());
+        }
+        Ok(palette)
+    }
+
+    /// Write a palette as a color scheme in this format
+    pub fn export(self, palette: &Palette) -> Result<String> {
+        let color = |slot: &str| palette.colors.get(slot).copied();
+        let ansi = |range: std::ops::Range<usize>| {
+            range.filter_map(move |i| Some((i, color(ANSI_NAMES[i].0)?)))
+        };
+        let string = |s: &str| Yaml::String(s.to_string());
+        let mut out = String::new();
+        match self {
+            PaletteFormat::Alacritty => {
+                let section = |slots: &[(&str, &str)]| {
+                    Yaml::Hash(
+                        slots
+                            .iter()
+                            .filter_map(|(key, slot)| {
+                                Some((string(key), string(&format!("#{}", hex(color(slot)?)))))
+                            })
+                            .collect(),
+                    )
+                };
+                let normal = ANSI_NAMES[..8].iter().map(|(n, _)| (*n, *n));
+                let bright = ANSI_NAMES[8..]
+                    .iter()
+                    .map(|(n, _)| (n.trim_start_matches("bright_"), *n));
+                let mut colors = Hash::new();
+                colors.insert(
+                    string("primary"),
+                    section(&[("background", "background"), ("foreground", "foreground")]),
+                );
+                if color("cursor").is_some() {
+                    colors.insert(string("cursor"), section(&[("cursor", "cursor")]));
+                }
+                colors.insert(string("normal"), section(&normal.collect::<Vec<_>>()));
+                colors.insert(string("bright"), section(&bright.collect::<Vec<_>>()));
+                let mut doc = Hash::new();
+                doc.insert(string("colors"), Yaml::Hash(colors));
+                out = DocumentFormat::Toml.to_string(&Yaml::Hash(doc))?;
+            }
+            PaletteFormat::Kitty => {
+                if let Some(name) = &palette.name {
+                    out.push_str(&format!("## name: {}\n", name));
+                }
+                for slot in ["foreground", "background", "cursor"] {
+                    if let Some(c) = color(slot) {
+                        out.push_str(&format!("{} #{}\n", slot, hex(c)));
+                    }
+                }
+                for (i, c) in ansi(0..16) {
+                    out.push_str(&format!("color{} #{}\n", i, hex(c)));
+                }
+            }
+            PaletteFormat::Foot => {
+                if let Some(name) = &palette.name {
+                    out.push_str(&format!("# {}\n", name));
+                }
+                if let Some(cursor) = color("cursor") {
+                    let text = color("background").unwrap_or((0, 0, 0));
+                    out.push_str(&format!(
+                        "[cursor]\ncolor={} {}\n\n",
+                        hex(text),
+                        hex(cursor)
+                    ));
+                }
+                out.push_str("[colors]\n");
+                for slot in ["foreground", "background"] {
+                    if let Some(c) = color(slot) {
+                        out.push_str(&format!("{}={}\n", slot, hex(c)));
+                    }
+                }
+                for (i, c) in ansi(0..16) {
+                    let key = if i < 8 { "regular" } else { "bright" };
+                    out.push_str(&format!("{}{}={}\n", key, i % 8, hex(c)));
+                }
+            }
+            PaletteFormat::Wezterm => {
+                let array = |range| {
+                    Yaml::Array(
+                        ansi(range)
+                            .map(|(_, c)| string(&format!("#{}", hex(c))))
+                            .collect(),
+                    )
+                };
+                let mut colors = Hash::new();
+                for (key, slot) in [
+                    ("foreground", "foreground"),
+                    ("background", "background"),
+                    ("cursor_bg", "cursor"),
+                ] {
+                    if let Some(c) = color(slot) {
+                        colors.insert(string(key), string(&format!("#{}", hex(c))));
+                    }
+                }
+                colors.insert(string("ansi"), array(0..8));
+                colors.insert(string("brights"), array(8..16));
+                let mut doc = Hash::new();
+                doc.insert(string("colors"), Yaml::Hash(colors));
+                if let Some(name) = &palette.name {
+                    let mut metadata = Hash::new();
+                    metadata.insert(string("name"), string(name));
+                    doc.insert(string("metadata"), Yaml::Hash(metadata));
+                }
+                out = DocumentFormat::Toml.to_string(&Yaml::Hash(doc))?;
+            }
+            PaletteFormat::Xresources => {
+                if let Some(name) = &palette.name {
+                    out.push_str(&format!("! {}\n", name));
+                }
+                for (key, slot) in [
+                    ("foreground", "foreground"),
+                    ("background", "background"),
+                    ("cursorColor", "cursor"),
+                ] {
+                    if let Some(c) = color(slot) {
+                        out.push_str(&format!("*.{}: #{}\n", key, hex(c)));
+                    }
+                }
+                for (i, c) in ansi(0..16) {
+                    out.push_str(&format!("*.color{}: #{}\n", i, hex(c)));
+                }
+            }
+            PaletteFormat::Iterm => {
+                out.push_str(concat!(
+                    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                    "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+                    "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+                    "<plist version=\"1.0\">\n<dict>\n"
+                ));
+                let mut entry = |key: &str, (r, g, b): Rgb| {
+                    out.push_str(&format!("\t<key>{}</key>\n\t<dict>\n", key));
+                    for (component, value) in [("Blue", b), ("Green", g), ("Red", r)] {
+                        out.push_str(&format!(
+                            "\t\t<key>{} Component</key>\n\t\t<real>{}</real>\n",
+                            component,
+                            f64::from(value) / 255.0
+                        ));
+                    }
+                    out.push_str("\t</dict>\n");
+                };
+                for (i, c) in ansi(0..16) {
+                    entry(&format!("Ansi {} Color", i), c);
+                }
+                for (key, slot) in [
+                    ("Foreground Color", "foreground"),
+                    ("Background Color", "background"),
+                    ("Cursor Color", "cursor"),
+                ] {
+                    if let Some(c) = color(slot) {
+                        entry(key, c);
+                    }
+                }
+                out.push_str("</dict>\n</plist>\n");
+            }
+            PaletteFormat::WindowsTerminal => {
+                let mut scheme = Hash::new();
+                if let Some(name) = &palette.name {
+                    scheme.insert(string("name"), string(name));
+                }
+                let mut insert = |key: &str, slot: &str| {
+                    if let Some(c) = color(slot) {
+                        scheme.insert(string(key), string(&format!("#{}", hex(c).to_uppercase())));
+                    }
+                };
+                insert("foreground", "foreground");
+                insert("background", "background");
+                insert("cursorColor", "cursor");
+                for (i, name) in WINDOWS_TERMINAL_NAMES.iter().enumerate() {
+                    let bright = format!("bright{}{}", name[..1].to_uppercase(), &name[1..]);
+                    insert(name, ANSI_NAMES[i].0);
+                    insert(&bright, ANSI_NAMES[i + 8].0);
+                }
+                out = DocumentFormat::Json.to_string(&Yaml::Hash(scheme))?;
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl FromStr for PaletteFormat {
+    type Err = VividError;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "alacritty" => Ok(PaletteFormat::Alacritty),
+            "kitty" => Ok(PaletteFormat::Kitty),
+            "foot" => Ok(PaletteFormat::Foot),
+            "wezterm" => Ok(PaletteFormat::Wezterm),
+            "xresources" => Ok(PaletteFormat::Xresources),
+            "iterm" => Ok(PaletteFormat::Iterm),
+            "windows-terminal" => Ok(PaletteFormat::WindowsTerminal),
+            _ => Err(VividError::UnknownPaletteFormat(s.to_string())),
+        }
+    }
+}
+
+fn yaml_color(value: &Yaml) -> Option<Rgb> {
+    value.as_str().and_then(parse_color)
+}
+
+/// Alacritty, in TOML or the older YAML format:
+/// `[colors.primary]`, `[colors.normal]`, `[colors.bright]` and `[colors.cursor]`
+fn alacritty(doc: &Yaml) -> Palette {
+    let colors = &doc["colors"];
+    let mut palette = Palette::default();
+    for (i, (name, _)) in ANSI_NAMES.iter().take(8).enumerate() {
+        palette.set_ansi(i, yaml_color(&colors["normal"][*name]));
+        palette.set_ansi(i + 8, yaml_color(&colors["bright"][*name]));
+    }
+    palette.set("foreground", yaml_color(&colors["primary"]["foreground"]));
+    palette.set("background", yaml_color(&colors["primary"]["background"]));
+    palette.set("cursor", yaml_color(&colors["cursor"]["cursor"]));
+    palette
+}
+
+/// WezTerm color scheme files: `ansi` and `brights` arrays in `[colors]`
+fn wezterm(doc: &Yaml) -> Palette {
+    let colors = &doc["colors"];
+    let mut palette = Palette {
+        name: doc["metadata"]["name"].as_str().map(String::from),
+        ..Palette::default()
+    };
+    for (offset, key) in [(0, "ansi"), (8, "brights")] {
+        if let Some(array) = colors[key].as_vec() {
+            for (i, value) in array.iter().take(8).enumerate() {
+                palette.set_ansi(offset + i, yaml_color(value));
+            }
+        }
+    }
+    palette.set("foreground", yaml_color(&colors["foreground"]));
+    palette.set("background", yaml_color(&colors["background"]));
+    palette.set("cursor", yaml_color(&colors["cursor_bg"]));
+    palette
+}
+
+/// The names of the normal ANSI colors in Windows Terminal schemes. The
+/// bright ones are `brightBlack` and so on.
+const WINDOWS_TERMINAL_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "purple", "cyan", "white",
+];
+
+/// A Windows Terminal color scheme, or a `settings.json` with a list of
+/// `schemes` (the first one is used)
+fn windows_terminal(doc: &Yaml) -> Palette {
+    let scheme = match &doc["schemes"] {
+        Yaml::Array(schemes) => schemes.first().unwrap_or(&Yaml::BadValue),
+        _ => doc,
+    };
+    let mut palette = Palette {
+        name: scheme["name"].as_str().map(String::from),
+        ..Palette::default()
+    };
+    for (i, name) in WINDOWS_TERMINAL_NAMES.iter().enumerate() {
+        palette.set_ansi(i, yaml_color(&scheme[*name]));
+        let bright = format!("bright{}{}", name[..1].to_uppercase(), &name[1..]);
+        palette.set_ansi(i + 8, yaml_color(&scheme[bright.as_str()]));
+    }
+    palette.set("foreground", yaml_color(&scheme["foreground"]));
+    palette.set("background", yaml_color(&scheme["background"]));
+    palette.set("cursor", yaml_color(&scheme["cursorColor"]));
+    palette
+}
+
+/// kitty: `color0` to `color15`, `foreground`, `background` and `cursor`,
+/// separated from their value by whitespace
+fn kitty(contents: &str) -> Palette {
+    let mut palette = Palette::default();
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let (key, value) = match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) => (key, parse_color(value)),
+            _ => continue,
+        };
+        match key {
+            "foreground" => palette.set("foreground", value),
+            "background" => palette.set("background", value),
+            "cursor" => palette.set("cursor", value),
+            _ => {
+                if let Some(index) = key.strip_prefix("color").and_then(|i| i.parse().ok()) {
+                    palette.set_ansi(index, value);
+                }
+            }
+        }
+    }
+    palette
+}
+
+/// foot: `regular0`..`regular7`, `bright0`..`bright7`, `foreground` and
+/// `background` in `[colors]` (or `[colors-dark]`), and the cursor as second
+/// value of `color` in `[cursor]`
+fn foot(contents: &str) -> Palette {
+    let mut palette = Palette::default();
+    let mut section = String::new();
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.to_string();
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+        match (section.as_str(), key) {
+            ("colors" | "colors-dark", "foreground") => {
+                palette.set("foreground", parse_color(value))
+            }
+            ("colors" | "colors-dark", "background") => {
+                palette.set("background", parse_color(value))
+            }
+            ("colors" | "colors-dark", _) => {
+                let index = key
+                    .strip_prefix("regular")
+                    .and_then(|i| i.parse::<usize>().ok())
+                    .filter(|i| *i < 8)
+                    .or_else(|| {
+                        key.strip_prefix("bright")
+                            .and_then(|i| i.parse::<usize>().ok())
+                            .filter(|i| *i < 8)
+                            .map(|i| i + 8)
+                    });
+                if let Some(index) = index {
+                    palette.set_ansi(index, parse_color(value));
+                }
+            }
+            ("cursor", "color") => palette.set(
+                "cursor",
+                value.split_whitespace().nth(1).and_then(parse_color),
+            ),
+            _ => {}
+        }
+    }
+    palette
+}
+
+/// X resources like `*.color0: #000000` or `URxvt.foreground: ...`, with
+/// support for `#define`d color names
+fn xresources(contents: &str) -> Palette {
+    let mut palette = Palette::default();
+    let mut defines = HashMap::new();
+    for line in contents.lines().map(str::trim) {
+        if let Some(define) = line.strip_prefix("#define") {
+            let mut parts = define.split_whitespace();
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                defines.insert(name.to_string(), value.to_string());
+            }
+            continue;
+        }
+        if line.starts_with('!') || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+        let value = defines.get(value).map_or(value, String::as_str);
+        let resource = key.rsplit(['.', '*']).next().unwrap_or(key);
+        match resource {
+            "foreground" => palette.set("foreground", parse_color(value)),
+            "background" => palette.set("background", parse_color(value)),
+            "cursorColor" => palette.set("cursor", parse_color(value)),
+            _ => {
+                if let Some(index) = resource.strip_prefix("color").and_then(|i| i.parse().ok()) {
+                    palette.set_ansi(index, parse_color(value));
+                }
+            }
+        }
+    }
+    palette
+}
+
+/// iTerm2 `.itermcolors`, an XML property list with a dictionary of color
+/// components per color (`Ansi 0 Color`, `Foreground Color`, ...)
+fn iterm(contents: &str) -> Result<Palette> {
+    let document = roxmltree::Document::parse_with_options(
+        contents,
+        roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..roxmltree::ParsingOptions::default()
+        },
+    )
+    .map_err(|e| VividError::InvalidPalette(e.to_string()))?;
+
+    let dict = document
+        .root_element()
+        .children()
+        .find(|node| node.has_tag_name("dict"))
+        .ok_or_else(|| VividError::InvalidPalette("expected an XML property list".into()))?;
+    let elements = dict.children().filter(|node| node.is_element());
+    let keys = elements.clone().step_by(2);
+    let values = elements.skip(1).step_by(2);
+
+    let mut palette = Palette::default();
+    for (key, value) in keys.zip(values) {
+        let color = iterm_color(value);
+        match key.text().unwrap_or_default() {
+            "Foreground Color" => palette.set("foreground", color),
+            "Background Color" => palette.set("background", color),
+            "Cursor Color" => palette.set("cursor", color),
+            key => {
+                let index = key
+                    .strip_prefix("Ansi ")
+                    .and_then(|k| k.strip_suffix(" Color"))
+                    .and_then(|i| i.parse().ok());
+                if let Some(index) = index {
+                    palette.set_ansi(index, color);
+                }
+            }
+        }
+    }
+    Ok(palette)
+}
+
+fn iterm_color(dict: roxmltree::Node) -> Option<Rgb> {
+    let elements = dict.children().filter(|node| node.is_element());
+    let components = elements
+        .clone()
+        .step_by(2)
+        .zip(elements.skip(1).step_by(2))
+        .filter_map(|(key, value)| Some((key.text()?, value.text()?.parse::<f64>().ok()?)));
+    let component = |name: &str| {
+        components
+            .clone()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+    };
+    Some((
+        component("Red Component")?,
+        component("Green Component")?,
+        component("Blue Component")?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{parse_color, Palette, PaletteFormat, SLOTS};
+    use crate::color::ColorMode;
+    use crate::document::DocumentFormat;
+    use crate::error::VividError;
+    use crate::terminal::Background;
+    use crate::theme::Theme;
+
+    fn import(file_name: &str, contents: &str) -> super::Palette {
+        let path = Path::new(file_name);
+        PaletteFormat::detect(path, contents)
+            .unwrap()
+            .parse(path, contents)
+            .unwrap()
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(Some((255, 0, 128)), parse_color("#ff0080"));
+        assert_eq!(Some((255, 0, 128)), parse_color("0xFF0080"));
+        assert_eq!(Some((255, 0, 128)), parse_color("'ff0080'"));
+        assert_eq!(Some((255, 0, 136)), parse_color("#f08"));
+        assert_eq!(Some((255, 0, 128)), parse_color("rgb:ff/00/80"));
+        assert_eq!(None, parse_color("red"));
+    }
+
+    #[test]
+    fn alacritty() {
+        let palette = import(
+            "dracula.toml",
+            "[colors.primary]\nbackground = '#282a36'\nforeground = '#f8f8f2'\n\n\
+             [colors.normal]\nblack = '#21222c'\nred = '#ff5555'\n\n\
+             [colors.bright]\nred = '#ff6e6e'\n",
+        );
+        assert_eq!(Some("dracula".into()), palette.name);
+        assert_eq!(Some(&(0x28, 0x2a, 0x36)), palette.colors.get("background"));
+        assert_eq!(Some(&(0xff, 0x55, 0x55)), palette.colors.get("red"));
+        assert_eq!(Some(&(0xff, 0x6e, 0x6e)), palette.colors.get("bright_red"));
+        assert_eq!(Some(Background::Dark), palette.background());
+
+        let yaml = import("old.yml", "colors:\n  normal:\n    blue: '0x0000ff'\n");
+        assert_eq!(Some(&(0, 0, 255)), yaml.colors.get("blue"));
+    }
+
+    #[test]
+    fn wezterm_and_windows_terminal() {
+        let wezterm = import(
+            "scheme.toml",
+            "[colors]\nansi = ['#000000', '#cc0000']\nbrights = ['#555555']\n\
+             background = '#ffffff'\ncursor_bg = '#00ff00'\n\n[metadata]\nname = 'Paper'\n",
+        );
+        assert_eq!(Some("Paper".into()), wezterm.name);
+        assert_eq!(Some(&(0xcc, 0, 0)), wezterm.colors.get("red"));
+        assert_eq!(
+            Some(&(0x55, 0x55, 0x55)),
+            wezterm.colors.get("bright_black")
+        );
+        assert_eq!(Some(&(0, 0xff, 0)), wezterm.colors.get("cursor"));
+        assert_eq!(Some(Background::Light), wezterm.background());
+
+        let windows_terminal = import(
+            "settings.json",
+            r##"{"schemes": [{"name": "Campbell", "purple": "#881798", "brightPurple": "#B4009E", "cursorColor": "#FFFFFF"}]}"##,
+        );
+        assert_eq!(Some("Campbell".into()), windows_terminal.name);
+        assert_eq!(
+            Some(&(0x88, 0x17, 0x98)),
+            windows_terminal.colors.get("magenta")
+        );
+        assert_eq!(
+            Some(&(0xb4, 0x00, 0x9e)),
+            windows_terminal.colors.get("bright_magenta")
+        );
+    }
+
+    #[test]
+    fn line_based_formats() {
+        let kitty = import(
+            "theme.conf",
+            "# comment\nforeground #dddddd\ncolor1   #ff0000\ncolor9 #ff8888\n",
+        );
+        assert_eq!(Some(&(0xdd, 0xdd, 0xdd)), kitty.colors.get("foreground"));
+        assert_eq!(Some(&(0xff, 0x88, 0x88)), kitty.colors.get("bright_red"));
+
+        let foot = import(
+            "foot.ini",
+            "[cursor]\ncolor=111111 222222\n\n[colors]\nbackground=000000\nregular2=00ff00\nbright7=ffffff\n",
+        );
+        assert_eq!(Some(&(0x22, 0x22, 0x22)), foot.colors.get("cursor"));
+        assert_eq!(Some(&(0, 0xff, 0)), foot.colors.get("green"));
+        assert_eq!(Some(&(0xff, 0xff, 0xff)), foot.colors.get("bright_white"));
+
+        let xresources = import(
+            ".Xresources",
+            "! comment\n#define base08 #ab4642\n*.color1: base08\nURxvt*background: #181818\n*cursorColor: rgb:ff/ff/ff\n",
+        );
+        assert_eq!(Some(&(0xab, 0x46, 0x42)), xresources.colors.get("red"));
+        assert_eq!(
+            Some(&(0x18, 0x18, 0x18)),
+            xresources.colors.get("background")
+        );
+        assert_eq!(Some(&(0xff, 0xff, 0xff)), xresources.colors.get("cursor"));
+    }
+
+    #[test]
+    fn iterm() {
+        let palette = import(
+            "Solarized.itermcolors",
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>Ansi 4 Color</key>
+	<dict>
+		<key>Blue Component</key>
+		<real>0.8</real>
+		<key>Green Component</key>
+		<real>0.5</real>
+		<key>Red Component</key>
+		<real>0.0</real>
+	</dict>
+	<key>Background Color</key>
+	<dict>
+		<key>Blue Component</key>
+		<real>0.2</real>
+		<key>Green Component</key>
+		<real>0.16</real>
+		<key>Red Component</key>
+		<real>0.0</real>
+	</dict>
+</dict>
+</plist>
+"#,
+        );
+        assert_eq!(Some(&(0, 128, 204)), palette.colors.get("blue"));
+        assert_eq!(Some(&(0, 41, 51)), palette.colors.get("background"));
+    }
+
+    #[test]
+    fn apply_to_theme() {
+        let palette = import(
+            "gruvbox.conf",
+            "background #282828\ncolor1 #cc241d\ncolor4 #458588\n",
+        );
+        let mut theme = DocumentFormat::Yaml
+            .parse(
+                "
+colors:
+  red: 'ansi:red'
+  blue: 'ansi:blue'
+  green: 'ansi:green'
+  orange: 'fe8019'
+meta:
+  name: ANSI
+  min-color-depth: ansi
+directory:
+  foreground: blue
+archives:
+  foreground: 'ansi:red'
+  background: 'ansi:black'
+",
+            )
+            .unwrap();
+        palette.apply_to_theme(&mut theme).unwrap();
+        // The palette has no green and black, so the theme still needs the
+        // terminal's colors
+        assert_eq!(
+            "meta:
+  min-color-depth: ansi
+  name: gruvbox
+  variant: dark
+colors:
+  red: cc241d
+  blue: \"458588\"
+  green: \"ansi:green\"
+  orange: fe8019
+directory:
+  foreground: blue
+archives:
+  foreground: cc241d
+  background: \"ansi:black\"
+",
+            DocumentFormat::Yaml.to_string(&theme).unwrap()
+        );
+
+        let mut theme = DocumentFormat::Yaml
+            .parse("colors:\n  red: 'ansi:red'\nmeta:\n  min-color-depth: ansi\n")
+            .unwrap();
+        palette.apply_to_theme(&mut theme).unwrap();
+        assert_eq!(
+            "meta:\n  name: gruvbox\n  variant: dark\ncolors:\n  red: cc241d\n",
+            DocumentFormat::Yaml.to_string(&theme).unwrap()
+        );
+    }
+
+    #[test]
+    fn from_theme() {
+        let theme = Theme::from_string(
+            "
+meta:
+  name: Example
+colors:
+  bg: '1a1b26'
+  fg: 'c0caf5'
+  comment: '565f89'
+  red1: 'f7768e'
+  red2: 'db4b4b'
+  blue: '7aa2f7'
+  teal: '1abc9c'
+  mauve: 'bb9af7'
+  terminal: 'ansi:green'
+",
+            ColorMode::BitDepth24,
+        )
+        .unwrap();
+        let palette = Palette::from_theme(&theme).unwrap();
+        let color = |slot: &str| palette.colors.get(slot).copied();
+
+        assert_eq!(Some("Example".into()), palette.name);
+        assert_eq!(Some((0x1a, 0x1b, 0x26)), color("background"));
+        assert_eq!(Some((0xc0, 0xca, 0xf5)), color("foreground"));
+        assert_eq!(Some((0x1a, 0x1b, 0x26)), color("black"));
+        assert_eq!(Some((0x56, 0x5f, 0x89)), color("bright_black"));
+        // The darker of two reds is the normal one
+        assert_eq!(Some((0xdb, 0x4b, 0x4b)), color("red"));
+        assert_eq!(Some((0xf7, 0x76, 0x8e)), color("bright_red"));
+        assert_eq!(Some((0x7a, 0xa2, 0xf7)), color("blue"));
+        assert_eq!(Some((0x1a, 0xbc, 0x9c)), color("cyan"));
+        assert_eq!(Some((0xbb, 0x9a, 0xf7)), color("magenta"));
+        assert!(SLOTS[..18].iter().all(|slot| color(slot).is_some()));
+        assert_eq!(None, color("cursor"));
+
+        let ansi = Theme::from_string("colors: {red: 'ansi:red'}", ColorMode::BitDepth24);
+        assert!(matches!(
+            Palette::from_theme(&ansi.unwrap()),
+            Err(VividError::NoRgbColors)
+        ));
+    }
+
+    #[test]
+    fn osc_sequences() {
+        let palette = import(
+            "scheme.conf",
+            "background #1a1b26\ncolor1 #ff0000\ncolor12 #0000ff\n",
+        );
+        assert_eq!(
+            vec![
+                "\x1b]4;1;rgb:ff/00/00\x07",
+                "\x1b]4;12;rgb:00/00/ff\x07",
+                "\x1b]11;rgb:1a/1b/26\x07"
+            ],
+            palette.osc_sequences()
+        );
+    }
+
+    #[test]
+    fn export_round_trip() {
+        let mut palette = import(
+            "scheme.conf",
+            "foreground #c0caf5\nbackground #1a1b26\ncursor #ff00ff\n",
+        );
+        for (i, (name, _)) in crate::color::ANSI_NAMES.iter().enumerate() {
+            palette
+                .colors
+                .insert(name, (i as u8 * 16, 255 - i as u8, 7));
+        }
+        palette.name = Some("Round \"trip\"".into());
+
+        for (format, file_name) in [
+            (PaletteFormat::Alacritty, "scheme.toml"),
+            (PaletteFormat::Kitty, "scheme.conf"),
+            (PaletteFormat::Foot, "scheme.ini"),
+            (PaletteFormat::Wezterm, "scheme.toml"),
+            (PaletteFormat::Xresources, ".Xresources"),
+            (PaletteFormat::Iterm, "scheme.itermcolors"),
+            (PaletteFormat::WindowsTerminal, "scheme.json"),
+        ] {
+            let exported = format.export(&palette).unwrap();
+            let path = Path::new(file_name);
+            assert_eq!(Some(format), PaletteFormat::detect(path, &exported));
+            let imported = format.parse(path, &exported).unwrap();
+            assert_eq!(palette.colors, imported.colors, "{:?}", format);
+        }
+    }
+}
@@ -0,0 +1,454 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, queue, terminal};
+
+use crate::color::ColorMode;
+use crate::error::Result;
+use crate::filetypes::FileTypes;
+use crate::theme::Theme;
+
+type ThemeLoader<'a> = Box<dyn Fn(&str, ColorMode) -> Result<Theme> + 'a>;
+
+/// What should happen after a key press
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Continue,
+    Quit,
+    Selected(String, ColorMode),
+}
+
+/// State of the interactive theme picker
+///
+/// The picker itself does not touch the terminal. It is driven by key events
+/// and renders complete frames as lines of text, which makes it possible to
+/// test it without a terminal.
+pub struct Picker<'a> {
+    themes: Vec<String>,
+    samples: Vec<(String, Vec<String>)>,
+    load_theme: ThemeLoader<'a>,
+    /// Themes that were shown before, or the error message of loading them
+    loaded: HashMap<(String, ColorMode), std::result::Result<Theme, String>>,
+    selected: usize,
+    color_mode: ColorMode,
+    filter: String,
+    editing_filter: bool,
+}
+
+impl<'a> Picker<'a> {
+    pub fn new<F>(
+        themes: Vec<String>,
+        filetypes: &FileTypes,
+        color_mode: ColorMode,
+        load: F,
+    ) -> Self
+    where
+        F: Fn(&str, ColorMode) -> Result<Theme> + 'a,
+    {
+        // Use one file type per category as a sample, preferring short keys
+        let mut samples: BTreeMap<&[String], &str> = BTreeMap::new();
+        for (key, category) in &filetypes.mapping {
+            let sample = samples.entry(category).or_insert(key);
+            if (key.len(), key.as_str()) < (sample.len(), *sample) {
+                *sample = key;
+            }
+        }
+
+        let mut picker = Picker {
+            themes,
+            samples: samples
+                .into_iter()
+                .map(|(category, key)| (key.to_string(), category.to_vec()))
+                .collect(),
+            load_theme: Box::new(load),
+            loaded: HashMap::new(),
+            selected: 0,
+            color_mode,
+            filter: String::new(),
+            editing_filter: false,
+        };
+        picker.load_selected_theme();
+        picker
+    }
+
+    /// Load the selected theme in the current color mode, unless it was
+    /// loaded before
+    fn load_selected_theme(&mut self) {
+        if let Some(name) = self.themes.get(self.selected) {
+            let key = (name.clone(), self.color_mode);
+            if !self.loaded.contains_key(&key) {
+                let theme = (self.load_theme)(name, self.color_mode).map_err(|e| e.to_string());
+                self.loaded.insert(key, theme);
+            }
+        }
+    }
+
+    pub fn selected_theme(&self) -> Option<&str> {
+        self.themes.get(self.selected).map(|s| s.as_str())
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Outcome {
+        // Raw mode turns Ctrl-C into a key press instead of a signal
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Outcome::Quit;
+        }
+        let outcome = self.handle_key_code(key.code);
+        self.load_selected_theme();
+        outcome
+    }
+
+    fn handle_key_code(&mut self, key: KeyCode) -> Outcome {
+        if self.editing_filter {
+            match key {
+                KeyCode::Enter | KeyCode::Esc => self.editing_filter = false,
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) => self.filter.push(c),
+                _ => {}
+            }
+            return Outcome::Continue;
+        }
+
+        match key {
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') if self.selected + 1 < self.themes.len() => {
+                self.selected += 1
+            }
+            KeyCode::Home | KeyCode::Char('g') => self.selected = 0,
+            KeyCode::End | KeyCode::Char('G') => {
+                self.selected = self.themes.len().saturating_sub(1)
+            }
+            KeyCode::Char('m') => {
+                self.color_mode = match self.color_mode {
+                    ColorMode::BitDepth24 => ColorMode::BitDepth8,
+                    ColorMode::BitDepth8 => ColorMode::BitDepth24,
+                }
+            }
+            KeyCode::Char('/') => {
+                self.filter.clear();
+                self.editing_filter = true;
+            }
+            KeyCode::Enter => {
+                if let Some(theme) = self.selected_theme() {
+                    return Outcome::Selected(theme.to_string(), self.color_mode);
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('q') => return Outcome::Quit,
+            _ => {}
+        }
+        Outcome::Continue
+    }
+
+    fn preview_lines(&self) -> Vec<String> {
+        let theme_name = match self.selected_theme() {
+            Some(name) => name,
+            None => return vec!["No themes available".into()],
+        };
+        let theme = match self.loaded.get(&(theme_name.to_string(), self.color_mode)) {
+            Some(Ok(theme)) => theme,
+            Some(Err(e)) => return vec![format!("Error: {}", e)],
+            None => return vec![],
+        };
+
+        self.samples
+            .iter()
+            .map(|(key, category)| (key, category, category.join(".")))
+            .filter(|(_, _, category_name)| category_name.contains(&self.filter))
+            .map(|(key, category, category_name)| {
+                let style = theme
+                    .get_terminal_style(category)
+                    .unwrap_or_else(|_| "0".into());
+                let name = sample_file_name(key, category);
+                let padding = " ".repeat(24usize.saturating_sub(name.chars().count()));
+                format!(
+                    "\x1b[{}m{}\x1b[0m{} {}",
+                    style, name, padding, category_name
+                )
+            })
+            .collect()
+    }
+
+    /// Render the full screen as a list of lines
+    pub fn render(&self, height: usize) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "j/k: select  m: color mode ({})  /: filter categories  Enter: choose  q: quit",
+                self.color_mode.name()
+            ),
+            if self.editing_filter || !self.filter.is_empty() {
+                format!(
+                    "filter: {}{}",
+                    self.filter,
+                    if self.editing_filter { "_" } else { "" }
+                )
+            } else {
+                String::new()
+            },
+        ];
+
+        let rows = height.saturating_sub(lines.len());
+        let list_width = self.themes.iter().map(|t| t.len()).max().unwrap_or(0) + 2;
+        let first_theme = (self.selected + 1).saturating_sub(rows);
+        let preview = self.preview_lines();
+
+        for row in 0..rows {
+            let theme = match self.themes.get(first_theme + row) {
+                Some(theme) if first_theme + row == self.selected => format!("> {}", theme),
+                Some(theme) => format!("  {}", theme),
+                None => String::new(),
+            };
+            let sample = preview.get(row).map(|s| s.as_str()).unwrap_or("");
+            lines.push(format!("{:width$} │ {}", theme, sample, width = list_width));
+        }
+        lines
+    }
+}
+
+/// A plausible file name that matches the given `LS_COLORS` key
+fn sample_file_name(key: &str, category: &[String]) -> String {
+    match key.strip_prefix('*') {
+        Some(suffix) if suffix.starts_with('.') => format!("example{}", suffix),
+        Some(name) => name.to_string(),
+        None => category.last().cloned().unwrap_or_default(),
+    }
+}
+
+/// Resets the terminal even if the picker returns early with an error
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn new() -> io::Result<TerminalGuard> {
+        terminal::enable_raw_mode()?;
+        // Lines are not truncated: escape sequences make it hard to determine
+        // their visible width, so we let the terminal clip them instead.
+        execute!(
+            io::stderr(),
+            terminal::EnterAlternateScreen,
+            terminal::DisableLineWrap,
+            cursor::Hide
+        )?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        execute!(
+            io::stderr(),
+            cursor::Show,
+            terminal::EnableLineWrap,
+            terminal::LeaveAlternateScreen
+        )
+        .ok();
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+/// Run the picker on the terminal (drawing to stderr) until a theme is chosen
+pub fn run(mut picker: Picker) -> Result<Option<(String, ColorMode)>> {
+    let _guard = TerminalGuard::new()?;
+    let mut stderr = io::stderr();
+
+    loop {
+        let (_, height) = terminal::size()?;
+        queue!(stderr, terminal::Clear(terminal::ClearType::All))?;
+        for (row, line) in picker.render(height as usize).iter().enumerate() {
+            queue!(stderr, cursor::MoveTo(0, row as u16))?;
+            write!(stderr, "{}", line)?;
+        }
+        stderr.flush()?;
+
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+            match picker.handle_key(key) {
+                Outcome::Continue => {}
+                Outcome::Quit => return Ok(None),
+                Outcome::Selected(theme, color_mode) => return Ok(Some((theme, color_mode))),
+            }
+        }
+    }
+}
+
+/// The line that sets `LS_COLORS` to the given theme in the user's shell. The
+/// color mode and output format are always given, so that the settings of
+/// the configuration file can not change what the command prints.
+pub fn shell_export_line(shell: &str, theme: &str, color_mode: ColorMode) -> String {
+    let mut command = format!(
+        "vivid -m {} generate --format ls-colors ",
+        color_mode.name()
+    );
+    if theme
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_./".contains(c))
+    {
+        command.push_str(theme);
+    } else {
+        command.push_str(&format!("'{}'", theme.replace('\'', "'\\''")));
+    }
+
+    if shell.ends_with("fish") {
+        format!("set -gx LS_COLORS ({})", command)
+    } else {
+        format!("export LS_COLORS=\"$({})\"", command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::{shell_export_line, Outcome, Picker};
+    use crate::color::ColorMode;
+    use crate::filetypes::FileTypes;
+    use crate::theme::Theme;
+
+    const THEME_A: &str = "
+        colors: {}
+        core:
+          directory:
+            foreground: '0000ff'
+        programming:
+          foreground: 'ff0000'
+    ";
+
+    const THEME_B: &str = "
+        colors: {}
+        core:
+          directory:
+            foreground: '00ff00'
+        programming:
+          font-style: bold
+    ";
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn picker<'a>(filetypes: &'a FileTypes, loads: &'a Cell<usize>) -> Picker<'a> {
+        Picker::new(
+            vec!["a".into(), "b".into()],
+            filetypes,
+            ColorMode::BitDepth24,
+            move |name, color_mode| {
+                loads.set(loads.get() + 1);
+                let contents = if name == "a" { THEME_A } else { THEME_B };
+                Theme::from_string(contents, color_mode)
+            },
+        )
+    }
+
+    fn filetypes() -> FileTypes {
+        FileTypes::from_string(
+            "
+                core:
+                  directory: [$di]
+                programming:
+                  rust: [.rs]
+                  python: [.py]
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn navigate_and_render() {
+        let filetypes = filetypes();
+        let loads = Cell::new(0);
+        let mut picker = picker(&filetypes, &loads);
+
+        let frame = picker.render(5);
+        assert_eq!(5, frame.len());
+        assert!(frame[2].starts_with("> a"));
+        assert!(frame[2].contains("\x1b[0;38;2;0;0;255mdirectory\x1b[0m"));
+        assert!(frame[3].contains("\x1b[0;38;2;255;0;0mexample.py\x1b[0m"));
+
+        assert_eq!(Outcome::Continue, picker.handle_key(key(KeyCode::Down)));
+        assert_eq!(Outcome::Continue, picker.handle_key(key(KeyCode::Down)));
+        let frame = picker.render(5);
+        assert!(frame[3].starts_with("> b"));
+        assert!(frame[2].contains("\x1b[0;38;2;0;255;0mdirectory\x1b[0m"));
+        assert!(frame[3].contains("\x1b[1mexample.py\x1b[0m"));
+
+        assert_eq!(
+            Outcome::Selected("b".into(), ColorMode::BitDepth24),
+            picker.handle_key(key(KeyCode::Enter))
+        );
+    }
+
+    #[test]
+    fn toggle_color_mode_and_filter() {
+        let filetypes = filetypes();
+        let loads = Cell::new(0);
+        let mut picker = picker(&filetypes, &loads);
+
+        for code in [
+            KeyCode::Char('m'),
+            KeyCode::Char('/'),
+            KeyCode::Char('r'),
+            KeyCode::Char('u'),
+            KeyCode::Enter,
+        ] {
+            assert_eq!(Outcome::Continue, picker.handle_key(key(code)));
+        }
+
+        let frame = picker.render(5);
+        assert!(frame[0].contains("8-bit"));
+        assert_eq!("filter: ru", frame[1]);
+        assert!(frame[2].contains("\x1b[0;38;5;196mexample.rs\x1b[0m"));
+        assert!(!frame.iter().any(|line| line.contains("directory")));
+
+        assert_eq!(Outcome::Quit, picker.handle_key(key(KeyCode::Char('q'))));
+    }
+
+    #[test]
+    fn themes_are_loaded_once() {
+        let filetypes = filetypes();
+        let loads = Cell::new(0);
+        let mut picker = picker(&filetypes, &loads);
+
+        for code in [
+            KeyCode::Down,
+            KeyCode::Up,
+            KeyCode::Down,
+            KeyCode::Char('m'),
+            KeyCode::Char('m'),
+        ] {
+            picker.handle_key(key(code));
+            picker.render(5);
+        }
+        // a and b in 24-bit mode, b in 8-bit mode
+        assert_eq!(3, loads.get());
+    }
+
+    #[test]
+    fn ctrl_c_quits() {
+        let filetypes = filetypes();
+        let loads = Cell::new(0);
+        let mut picker = picker(&filetypes, &loads);
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+
+        assert_eq!(
+            Outcome::Continue,
+            picker.handle_key(key(KeyCode::Char('/')))
+        );
+        assert_eq!(Outcome::Quit, picker.handle_key(ctrl_c));
+    }
+
+    #[test]
+    fn export_line() {
+        assert_eq!(
+            "export LS_COLORS=\"$(vivid -m 24-bit generate --format ls-colors molokai)\"",
+            shell_export_line("/bin/bash", "molokai", ColorMode::BitDepth24)
+        );
+        assert_eq!(
+            "set -gx LS_COLORS (vivid -m 8-bit generate --format ls-colors 'my theme')",
+            shell_export_line("/usr/bin/fish", "my theme", ColorMode::BitDepth8)
+        );
+    }
+}
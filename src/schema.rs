@@ -0,0 +1,208 @@
+use std::str::FromStr;
+
+use crate::error::{Result, VividError};
+
+/// The JSON schemas in `schemas/`, which describe the files that vivid reads
+/// and writes. They are embedded so that `vivid schema` always prints the
+/// version that matches the binary.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Schema {
+    Theme,
+    FileTypes,
+    Export,
+}
+
+impl Schema {
+    pub const NAMES: [&'static str; 3] = ["theme", "filetypes", "export"];
+
+    pub fn contents(self) -> &'static str {
+        match self {
+            Schema::Theme => include_str!("../schemas/theme.json"),
+            Schema::FileTypes => include_str!("../schemas/filetypes.json"),
+            Schema::Export => include_str!("../schemas/export.json"),
+        }
+    }
+}
+
+impl FromStr for Schema {
+    type Err = VividError;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "theme" => Ok(Schema::Theme),
+            "filetypes" => Ok(Schema::FileTypes),
+            "export" => Ok(Schema::Export),
+            _ => Err(VividError::UnknownSchema(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use jsonschema::Validator;
+    use serde_json::{json, Value};
+
+    use super::Schema;
+    use crate::color::{ColorMode, ANSI_NAMES};
+    use crate::document::DocumentFormat;
+    use crate::export;
+    use crate::filetypes::FileTypes;
+    use crate::font_style::FontStyle;
+    use crate::lsd;
+    use crate::theme::Theme;
+    use crate::tools;
+
+    fn validator(schema: Schema) -> Validator {
+        let schema: Value = serde_json::from_str(schema.contents()).unwrap();
+        jsonschema::validator_for(&schema).unwrap()
+    }
+
+    /// Read a YAML file like the bundled themes as JSON
+    fn load_as_json(path: &Path) -> Value {
+        let doc = DocumentFormat::Yaml
+            .parse(&fs::read_to_string(path).unwrap())
+            .unwrap();
+        serde_json::from_str(&DocumentFormat::Json.to_string(&doc).unwrap()).unwrap()
+    }
+
+    fn errors(validator: &Validator, instance: &Value) -> Vec<String> {
+        validator
+            .iter_errors(instance)
+            .map(|e| format!("{}: {}", e.instance_path, e))
+            .collect()
+    }
+
+    #[test]
+    fn bundled_themes_match_schema() {
+        let validator = validator(Schema::Theme);
+        let themes = Path::new(env!("CARGO_MANIFEST_DIR")).join("themes");
+        for theme in fs::read_dir(themes).unwrap() {
+            let path = theme.unwrap().path();
+            let errors = errors(&validator, &load_as_json(&path));
+            assert!(errors.is_empty(), "{}: {:?}", path.display(), errors);
+        }
+    }
+
+    #[test]
+    fn bundled_database_matches_schema() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("config/filetypes.yml");
+        let database = load_as_json(&path);
+        assert_eq!(
+            Vec::<String>::new(),
+            errors(&validator(Schema::FileTypes), &database)
+        );
+
+        let case_insensitive =
+            json!({"media": {"image": {"case-insensitive": true, "png": [".png"]}}});
+        assert!(validator(Schema::FileTypes).is_valid(&case_insensitive));
+    }
+
+    /// The values that the schema allows are the ones that the code accepts
+    #[test]
+    fn theme_schema_matches_code() {
+        let validator = validator(Schema::Theme);
+        let with_color = |color: &str| json!({"colors": {"c": color}});
+        let with_font_style = |style: &str| json!({"x": {"font-style": style}});
+
+        for (name, _) in ANSI_NAMES.iter() {
+            let color = format!("ansi:{}", name);
+            assert!(color.parse::<crate::color::Color>().is_ok());
+            assert!(validator.is_valid(&with_color(&color)), "{}", color);
+        }
+        for color in &["ansi:purple", "ansi:", "12345", "#ff0000"] {
+            assert!(color.parse::<crate::color::Color>().is_err());
+            assert!(!validator.is_valid(&with_color(color)), "{}", color);
+        }
+
+        let schema: Value = serde_json::from_str(Schema::Theme.contents()).unwrap();
+        let font_styles = schema["definitions"]["font_style"]["enum"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|style| style.as_str().unwrap())
+            .collect::<Vec<_>>();
+        let mut known = FontStyle::all_names();
+        known.sort_unstable();
+        let mut listed = font_styles.clone();
+        listed.sort_unstable();
+        assert_eq!(known, listed);
+        for style in font_styles {
+            assert!(validator.is_valid(&with_font_style(style)));
+        }
+        assert!(!validator.is_valid(&with_font_style("wavy")));
+
+        assert!(validator.is_valid(&json!({"base16": "schemes/ocean.yaml"})));
+
+        let tools = schema["properties"]["tools"]["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tools::SLOTS
+                .iter()
+                .map(|(slot, _)| *slot)
+                .collect::<Vec<_>>(),
+            tools
+        );
+        assert!(!validator.is_valid(&json!({"tools": {"grep-match": {}}})));
+
+        let lsd = schema["properties"]["lsd"]["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let mut sections = lsd::SLOTS
+            .iter()
+            .map(|(slot, _)| slot.split('.').next().unwrap())
+            .collect::<Vec<_>>();
+        sections.dedup();
+        assert_eq!(sections, lsd);
+        assert!(
+            validator.is_valid(&json!({"lsd": {"permission": {"read": {"foreground": "00ff00"}}}}))
+        );
+        assert!(!validator.is_valid(&json!({"lsd": {"permissions": {}}})));
+        assert!(!validator.is_valid(&json!({"base16": {"base00": "000000"}})));
+    }
+
+    #[test]
+    fn export_matches_schema() {
+        let theme = Theme::from_string(
+            "
+                colors:
+                  blue: '0000ff'
+                core:
+                  directory:
+                    foreground: blue
+                    underline-color: 'ansi:red'
+                programming:
+                  foreground: 'ansi:red'
+            ",
+            ColorMode::BitDepth8,
+        )
+        .unwrap();
+        let filetypes = FileTypes::from_string(
+            "
+                core:
+                  directory: [$di]
+                programming:
+                  source:
+                    rust: [.rs, 'Cargo.*']
+            ",
+        )
+        .unwrap();
+
+        let entries = export::resolve(&filetypes, &theme).unwrap();
+        let mut out = Vec::new();
+        export::write_json(&mut out, "example", &theme, &entries).unwrap();
+        let export: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            Vec::<String>::new(),
+            errors(&validator(Schema::Export), &export)
+        );
+    }
+}
@@ -0,0 +1,468 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::color::ANSI_NAMES;
+use crate::palette::Palette;
+
+/// How long to wait for the terminal to answer a query
+pub const QUERY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A terminal that can be queried with escape sequences
+///
+/// This is implemented for the controlling terminal, and by scripted stand-ins
+/// in tests.
+pub trait Tty {
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()>;
+
+    /// Read whatever is available, waiting at most `timeout`. Returns `Ok(0)`
+    /// if nothing arrived in time.
+    fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize>;
+}
+
+#[cfg(unix)]
+mod controlling {
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Read, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::time::Duration;
+
+    use crossterm::terminal;
+
+    /// The controlling terminal (`/dev/tty`), in raw mode while this is alive
+    pub struct ControllingTty {
+        file: File,
+        was_raw: bool,
+    }
+
+    impl ControllingTty {
+        pub fn open() -> io::Result<ControllingTty> {
+            let file = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+            let was_raw = terminal::is_raw_mode_enabled()?;
+            if !was_raw {
+                terminal::enable_raw_mode()?;
+            }
+            Ok(ControllingTty { file, was_raw })
+        }
+    }
+
+    impl Drop for ControllingTty {
+        fn drop(&mut self) {
+            if !self.was_raw {
+                terminal::disable_raw_mode().ok();
+            }
+        }
+    }
+
+    impl super::Tty for ControllingTty {
+        fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+            self.file.write_all(data)?;
+            self.file.flush()
+        }
+
+        fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+            let mut fds = libc::pollfd {
+                fd: self.file.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+            match unsafe { libc::poll(&mut fds, 1, timeout_ms) } {
+                -1 => Err(io::Error::last_os_error()),
+                0 => Ok(0),
+                _ => self.file.read(buf),
+            }
+        }
+    }
+}
+
+/// Open the controlling terminal for queries, if there is one
+pub fn controlling_tty() -> Option<Box<dyn Tty>> {
+    #[cfg(unix)]
+    {
+        controlling::ControllingTty::open()
+            .ok()
+            .map(|tty| Box::new(tty) as Box<dyn Tty>)
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Primary device attributes request. Virtually every terminal answers it, so
+/// sending it after a query tells us when to stop waiting for an answer.
+const DEVICE_ATTRIBUTES: &[u8] = b"\x1b[c";
+
+/// Send `query` and collect the answer until the terminal has responded to the
+/// device attributes request that follows it, or until `timeout` has passed
+pub fn query(tty: &mut dyn Tty, query: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+    let mut request = query.to_vec();
+    request.extend_from_slice(DEVICE_ATTRIBUTES);
+    tty.write_all(&request)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut response = Vec::new();
+    let mut buf = [0; 256];
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        let n = tty.read_timeout(&mut buf, remaining)?;
+        if n == 0 {
+            break;
+        }
+        response.extend_from_slice(&buf[..n]);
+        if let Some(start) = find(&response, b"\x1b[?") {
+            if response[start..].contains(&b'c') {
+                response.truncate(start);
+                break;
+            }
+        }
+    }
+    Ok(response)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Parse an X11 color specification like `rgb:ffff/8080/0000`
+pub fn parse_x11_color(spec: &str) -> Option<(u8, u8, u8)> {
+    let components: Vec<u8> = spec
+        .strip_prefix("rgb:")?
+        .split('/')
+        .map(|c| {
+            let value = u32::from_str_radix(c, 16).ok()?;
+            let max = match c.len() {
+                1..=4 => (1u32 << (4 * c.len())) - 1,
+                _ => return None,
+            };
+            Some(((value * 255 + max / 2) / max) as u8)
+        })
+        .collect::<Option<_>>()?;
+    match components[..] {
+        [r, g, b] => Some((r, g, b)),
+        _ => None,
+    }
+}
+
+/// Extract the color from an answer to an OSC color query, like
+/// `ESC ] 11 ; rgb:0000/0000/0000 BEL`
+pub fn parse_osc_color_response(response: &[u8], prefix: &str) -> Option<(u8, u8, u8)> {
+    let response = String::from_utf8_lossy(response);
+    let start = response.find(&format!("\x1b]{}", prefix))? + 2 + prefix.len();
+    let rest = &response[start..];
+    let end = rest.find(['\x07', '\x1b'])?;
+    parse_x11_color(&rest[..end])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background {
+    Light,
+    Dark,
+}
+
+impl Background {
+    pub fn name(self) -> &'static str {
+        match self {
+            Background::Light => "light",
+            Background::Dark => "dark",
+        }
+    }
+
+    pub fn from_rgb((r, g, b): (u8, u8, u8)) -> Background {
+        let luminance = 0.2126 * f64::from(r) + 0.7152 * f64::from(g) + 0.0722 * f64::from(b);
+        if luminance > 127.5 {
+            Background::Light
+        } else {
+            Background::Dark
+        }
+    }
+
+    /// Interpret `COLORFGBG`, which is set by some terminals to `fg;bg` or `fg;default;bg`
+    fn from_colorfgbg(value: &str) -> Option<Background> {
+        match value.rsplit(';').next()?.parse::<u8>().ok()? {
+            0..=6 | 8 => Some(Background::Dark),
+            7 | 9..=15 => Some(Background::Light),
+            _ => None,
+        }
+    }
+}
+
+/// How the terminal background was determined
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackgroundSource {
+    Query,
+    ColorFgBg,
+    Environment,
+    Default,
+}
+
+/// Determine whether the terminal has a light or a dark background by asking
+/// the terminal (OSC 11), then looking at `COLORFGBG` and finally at `VIVID_BACKGROUND`
+pub fn detect_background<E>(tty: Option<&mut dyn Tty>, env: E) -> (Background, BackgroundSource)
+where
+    E: Fn(&str) -> Option<String>,
+{
+    let queried = tty.and_then(|tty| {
+        let response = query(tty, b"\x1b]11;?\x1b\\", QUERY_TIMEOUT).ok()?;
+        parse_osc_color_response(&response, "11;")
+    });
+    if let Some(rgb) = queried {
+        return (Background::from_rgb(rgb), BackgroundSource::Query);
+    }
+
+    if let Some(background) = env("COLORFGBG").and_then(|v| Background::from_colorfgbg(&v)) {
+        return (background, BackgroundSource::ColorFgBg);
+    }
+
+    match env("VIVID_BACKGROUND").as_deref() {
+        Some("light") => (Background::Light, BackgroundSource::Environment),
+        Some("dark") => (Background::Dark, BackgroundSource::Environment),
+        _ => (Background::Dark, BackgroundSource::Default),
+    }
+}
+
+/// Ask the terminal for the colors of its 16-color palette (OSC 4) and its
+/// default foreground and background colors (OSC 10 and 11). Colors that the
+/// terminal does not report are missing from the result.
+pub fn query_palette(tty: &mut dyn Tty) -> io::Result<Palette> {
+    let mut request = String::new();
+    for index in 0..ANSI_NAMES.len() {
+        request.push_str(&format!("\x1b]4;{};?\x1b\\", index));
+    }
+    request.push_str("\x1b]10;?\x1b\\\x1b]11;?\x1b\\");
+    let response = query(tty, request.as_bytes(), QUERY_TIMEOUT)?;
+
+    let mut palette = Palette::default();
+    for (index, (name, _)) in ANSI_NAMES.iter().enumerate() {
+        if let Some(color) = parse_osc_color_response(&response, &format!("4;{};", index)) {
+            palette.colors.insert(name, color);
+        }
+    }
+    for (prefix, slot) in [("10;", "foreground"), ("11;", "background")] {
+        if let Some(color) = parse_osc_color_response(&response, prefix) {
+            palette.colors.insert(slot, color);
+        }
+    }
+    Ok(palette)
+}
+
+/// A terminal multiplexer, which only forwards escape sequences to the outer
+/// terminal if they are wrapped in a DCS passthrough sequence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Multiplexer {
+    Tmux,
+    Screen,
+}
+
+impl Multiplexer {
+    /// Detect tmux by `TMUX` and GNU screen by `STY` or a `TERM` of `screen*`
+    pub fn detect<E>(env: E) -> Option<Multiplexer>
+    where
+        E: Fn(&str) -> Option<String>,
+    {
+        if env("TMUX").is_some() {
+            Some(Multiplexer::Tmux)
+        } else if env("STY").is_some() || env("TERM").is_some_and(|t| t.starts_with("screen")) {
+            Some(Multiplexer::Screen)
+        } else {
+            None
+        }
+    }
+
+    /// Wrap an escape sequence so that it reaches the outer terminal. The
+    /// sequence must not contain a string terminator (`ESC \\`), so OSC
+    /// sequences have to end in BEL.
+    pub fn wrap(self, sequence: &str) -> String {
+        match self {
+            Multiplexer::Tmux => {
+                format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+            }
+            Multiplexer::Screen => format!("\x1bP{}\x1b\\", sequence),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::io;
+    use std::time::Duration;
+
+    use super::{
+        detect_background, parse_osc_color_response, parse_x11_color, query, Background,
+        BackgroundSource, Multiplexer, Tty,
+    };
+    use crate::terminal::query_palette;
+
+    /// A stand-in for a terminal that answers known queries with canned responses
+    struct ScriptedTty {
+        answers: Vec<(Vec<u8>, Vec<u8>)>,
+        pending: VecDeque<u8>,
+        written: Vec<u8>,
+        answers_device_attributes: bool,
+    }
+
+    impl ScriptedTty {
+        fn new(answers: &[(&str, &str)]) -> ScriptedTty {
+            ScriptedTty {
+                answers: answers
+                    .iter()
+                    .map(|(q, a)| (q.as_bytes().to_vec(), a.as_bytes().to_vec()))
+                    .collect(),
+                pending: VecDeque::new(),
+                written: Vec::new(),
+                answers_device_attributes: true,
+            }
+        }
+    }
+
+    impl Tty for ScriptedTty {
+        fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+            self.written.extend_from_slice(data);
+            let mut rest = data;
+            while !rest.is_empty() {
+                if let Some((query, answer)) =
+                    self.answers.iter().find(|(q, _)| rest.starts_with(q))
+                {
+                    self.pending.extend(answer);
+                    rest = &rest[query.len()..];
+                } else if rest.starts_with(b"\x1b[c") {
+                    if self.answers_device_attributes {
+                        self.pending.extend(b"\x1b[?62;22c");
+                    }
+                    rest = &rest[3..];
+                } else {
+                    rest = &rest[1..];
+                }
+            }
+            Ok(())
+        }
+
+        fn read_timeout(&mut self, buf: &mut [u8], _timeout: Duration) -> io::Result<usize> {
+            // Answer in small chunks, like a real terminal might
+            let n = buf.len().min(self.pending.len()).min(7);
+            for b in buf.iter_mut().take(n) {
+                *b = self.pending.pop_front().unwrap();
+            }
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn x11_colors() {
+        assert_eq!(Some((255, 128, 0)), parse_x11_color("rgb:ffff/8080/0000"));
+        assert_eq!(Some((255, 136, 0)), parse_x11_color("rgb:f/8/0"));
+        assert_eq!(Some((0x12, 0x34, 0x56)), parse_x11_color("rgb:12/34/56"));
+        assert_eq!(None, parse_x11_color("rgb:12/34"));
+        assert_eq!(None, parse_x11_color("#123456"));
+    }
+
+    #[test]
+    fn query_stops_at_device_attributes() {
+        let mut tty = ScriptedTty::new(&[("\x1b]11;?\x1b\\", "\x1b]11;rgb:fdfd/f6f6/e3e3\x1b\\")]);
+        let response = query(&mut tty, b"\x1b]11;?\x1b\\", Duration::from_secs(1)).unwrap();
+        assert_eq!(b"\x1b]11;rgb:fdfd/f6f6/e3e3\x1b\\".to_vec(), response);
+        assert_eq!(
+            Some((0xfd, 0xf6, 0xe3)),
+            parse_osc_color_response(&response, "11;")
+        );
+    }
+
+    #[test]
+    fn background_from_query() {
+        let mut tty = ScriptedTty::new(&[("\x1b]11;?\x1b\\", "\x1b]11;rgb:2828/2c2c/3434\x07")]);
+        assert_eq!(
+            (Background::Dark, BackgroundSource::Query),
+            detect_background(Some(&mut tty), |_| Some("0;15".into()))
+        );
+
+        let mut tty = ScriptedTty::new(&[("\x1b]11;?\x1b\\", "\x1b]11;rgb:fafa/fafa/fafa\x07")]);
+        assert_eq!(
+            (Background::Light, BackgroundSource::Query),
+            detect_background(Some(&mut tty), |_| None)
+        );
+    }
+
+    #[test]
+    fn background_fallbacks() {
+        // A terminal that does not support OSC 11 only answers the device attributes
+        let mut tty = ScriptedTty::new(&[]);
+        assert_eq!(
+            (Background::Light, BackgroundSource::ColorFgBg),
+            detect_background(Some(&mut tty), |var| match var {
+                "COLORFGBG" => Some("0;default;15".into()),
+                _ => None,
+            })
+        );
+
+        let env = |var: &str| match var {
+            "VIVID_BACKGROUND" => Some("light".into()),
+            _ => None,
+        };
+        assert_eq!(
+            (Background::Light, BackgroundSource::Environment),
+            detect_background(None, env)
+        );
+
+        assert_eq!(
+            (Background::Dark, BackgroundSource::Default),
+            detect_background(None, |_| None)
+        );
+    }
+
+    #[test]
+    fn palette_from_queries() {
+        let mut tty = ScriptedTty::new(&[
+            ("\x1b]4;1;?\x1b\\", "\x1b]4;1;rgb:cccc/2424/1d1d\x1b\\"),
+            ("\x1b]4;10;?\x1b\\", "\x1b]4;10;rgb:b8/bb/26\x07"),
+            ("\x1b]11;?\x1b\\", "\x1b]11;rgb:2828/2828/2828\x1b\\"),
+        ]);
+        let palette = query_palette(&mut tty).unwrap();
+        assert_eq!(Some(&(0xcc, 0x24, 0x1d)), palette.colors.get("red"));
+        assert_eq!(
+            Some(&(0xb8, 0xbb, 0x26)),
+            palette.colors.get("bright_green")
+        );
+        assert_eq!(Some(&(0x28, 0x28, 0x28)), palette.colors.get("background"));
+        assert_eq!(3, palette.colors.len());
+
+        // Terminals that do not support the queries only answer the device attributes
+        let mut tty = ScriptedTty::new(&[]);
+        assert!(query_palette(&mut tty).unwrap().colors.is_empty());
+    }
+
+    #[test]
+    fn multiplexer_passthrough() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |var: &str| {
+                vars.iter()
+                    .find(|(name, _)| *name == var)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        assert_eq!(
+            Some(Multiplexer::Tmux),
+            Multiplexer::detect(env(&[
+                ("TMUX", "/tmp/tmux-1000/default,1,0"),
+                ("TERM", "screen")
+            ]))
+        );
+        assert_eq!(
+            Some(Multiplexer::Screen),
+            Multiplexer::detect(env(&[("TERM", "screen.xterm-256color")]))
+        );
+        assert_eq!(
+            None,
+            Multiplexer::detect(env(&[("TERM", "xterm-256color")]))
+        );
+
+        let osc = "\x1b]11;rgb:00/00/00\x07";
+        assert_eq!(
+            "\x1bPtmux;\x1b\x1b]11;rgb:00/00/00\x07\x1b\\",
+            Multiplexer::Tmux.wrap(osc)
+        );
+        assert_eq!(
+            "\x1bP\x1b]11;rgb:00/00/00\x07\x1b\\",
+            Multiplexer::Screen.wrap(osc)
+        );
+    }
+}
@@ -4,29 +4,123 @@ use std::path::Path;
 use yaml_rust::yaml::YamlLoader;
 use yaml_rust::Yaml;
 
+use crate::base16;
 use crate::color::{Color, ColorMode, ColorType};
+use crate::document::DocumentFormat;
 use crate::error::{Result, VividError};
 use crate::font_style::FontStyle;
-use crate::types::CategoryRef;
+use crate::palette::Palette;
+use crate::types::{Category, CategoryRef};
 use crate::util::{load_yaml_file, transpose};
 
+/// Whether a theme is meant for a light or a dark terminal background
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variant {
+    Light,
+    Dark,
+}
+
+impl Variant {
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Light => "light",
+            Variant::Dark => "dark",
+        }
+    }
+}
+
+/// The optional `meta` section of a theme
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ThemeMeta {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub license: Option<String>,
+    pub variant: Option<Variant>,
+    pub url: Option<String>,
+    pub min_color_depth: Option<String>,
+}
+
+impl ThemeMeta {
+    fn from_yaml(meta: &Yaml) -> Result<ThemeMeta> {
+        let map = match meta {
+            Yaml::BadValue => return Ok(ThemeMeta::default()),
+            Yaml::Hash(map) => map,
+            _ => return Err(VividError::UnexpectedYamlType),
+        };
+
+        let mut result = ThemeMeta::default();
+        for (key, value) in map {
+            let value = value.as_str().ok_or(VividError::UnexpectedYamlType)?;
+            match key.as_str() {
+                Some("name") => result.name = Some(value.into()),
+                Some("author") => result.author = Some(value.into()),
+                Some("license") => result.license = Some(value.into()),
+                Some("variant") => {
+                    result.variant = Some(match value {
+                        "light" => Variant::Light,
+                        "dark" => Variant::Dark,
+                        _ => {
+                            return Err(VividError::InvalidThemeMeta(
+                                "variant".into(),
+                                value.into(),
+                            ))
+                        }
+                    })
+                }
+                Some("url") => result.url = Some(value.into()),
+                Some("min-color-depth") => match value {
+                    "ansi" | "8-bit" | "24-bit" => result.min_color_depth = Some(value.into()),
+                    _ => {
+                        return Err(VividError::InvalidThemeMeta(
+                            "min-color-depth".into(),
+                            value.into(),
+                        ))
+                    }
+                },
+                _ => {
+                    return Err(VividError::InvalidThemeMeta(
+                        key.as_str().unwrap_or("?").into(),
+                        value.into(),
+                    ))
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Keys that make a theme node a style rather than a group of categories
+const STYLE_KEYS: &[&str] = &["foreground", "background", "underline-color", "font-style"];
+
 #[derive(Debug)]
 pub struct Theme {
+    pub meta: ThemeMeta,
     colors: HashMap<String, Color>,
     categories: Yaml, // TODO: load the category tree into a proper data structure
     color_mode: ColorMode,
+    terminal_palette: Option<Palette>,
 }
 
 impl Theme {
+    /// Load a theme in the format given by the file extension (YAML, TOML or JSON).
+    /// A `base16:` scheme path is resolved against the directory of the theme.
     pub fn from_path(path: &Path, color_mode: ColorMode) -> Result<Theme> {
         let contents = load_yaml_file(path)
             .map_err(|_| VividError::CouldNotLoadTheme(path.to_string_lossy().into()))?;
-        Self::from_string(&contents, color_mode)
+        let doc = DocumentFormat::from_path(path).parse(&contents)?;
+        Self::from_document(base16::expand(doc, path.parent())?, color_mode)
     }
 
     pub(crate) fn from_string(contents: &str, color_mode: ColorMode) -> Result<Theme> {
         let mut docs = YamlLoader::load_from_str(contents)?;
         let doc = docs.pop().ok_or(VividError::EmptyThemeFile)?;
+        Self::from_document(base16::expand(doc, None)?, color_mode)
+    }
+
+    pub fn from_document(doc: Yaml, color_mode: ColorMode) -> Result<Theme> {
+        if doc.is_null() {
+            return Err(VividError::EmptyThemeFile);
+        }
 
         let mut colors = HashMap::new();
 
@@ -45,12 +139,26 @@ impl Theme {
         }
 
         Ok(Theme {
+            meta: ThemeMeta::from_yaml(&doc["meta"])?,
             colors,
             categories: doc,
             color_mode,
+            terminal_palette: None,
         })
     }
 
+    /// The named colors of the `colors` section
+    pub fn colors(&self) -> &HashMap<String, Color> {
+        &self.colors
+    }
+
+    /// Whether any of the named colors refers to the terminal's ANSI palette
+    pub fn uses_ansi_colors(&self) -> bool {
+        self.colors
+            .values()
+            .any(|color| matches!(color, Color::Ansi3Bit(_)))
+    }
+
     fn get_color(&self, color_str: &str) -> Result<Color> {
         self.colors
             .get(color_str)
@@ -59,18 +167,20 @@ impl Theme {
             .ok_or_else(|| VividError::UnknownColor(color_str.to_string()))
     }
 
-    pub fn get_style(&self, category: CategoryRef) -> Result<String> {
+    /// Find the theme node for a category and resolve its colors
+    pub fn resolve_style(&self, category: CategoryRef) -> Result<Style> {
         if category.is_empty() {
             // TODO: use a non-empty collection data type to avoid this
             panic!("category should not be empty");
         }
 
         let mut item = &self.categories;
+        let mut node = Vec::new();
         for key in category {
             if let Yaml::Hash(map) = item {
-                if (map.contains_key(&Yaml::String("foreground".into()))
-                    || map.contains_key(&Yaml::String("background".into()))
-                    || map.contains_key(&Yaml::String("font-style".into())))
+                if STYLE_KEYS
+                    .iter()
+                    .any(|k| map.contains_key(&Yaml::String((*k).into())))
                     && map.get(&Yaml::String(key.clone())).is_none()
                 {
                     // We can not specialize further
@@ -79,6 +189,7 @@ impl Theme {
 
                 if let Some(value) = map.get(&Yaml::String(key.clone())) {
                     item = value;
+                    node.push(key.clone());
                 } else {
                     return Err(VividError::CouldNotFindStyleFor(category.join(".")));
                 }
@@ -102,33 +213,99 @@ impl Theme {
 
             let background = transpose(background.map(|fg| self.get_color(fg)))?;
 
-            let mut style: String = format!("{font_style}");
-            if let Some(foreground) = foreground {
-                let foreground_code = foreground.get_style(ColorType::Foreground, self.color_mode);
-                style.push_str(&format!(
-                    ";{foreground_code}",
-                    foreground_code = foreground_code
-                ));
-            }
+            let underline_color = map.get(&Yaml::String("underline-color".into())).map(|s| {
+                s.as_str()
+                    .expect("'underline-color' value should be a string")
+            });
 
-            if let Some(background) = background {
-                let background_code = background.get_style(ColorType::Background, self.color_mode);
-                style.push_str(&format!(
-                    ";{background_code}",
-                    background_code = background_code
-                ));
-            }
+            let underline_color = transpose(underline_color.map(|c| self.get_color(c)))?;
 
-            Ok(style)
+            Ok(Style {
+                node,
+                font_style,
+                foreground,
+                background,
+                underline_color,
+            })
         } else {
             Err(VividError::UnexpectedYamlType)
         }
     }
+
+    pub fn color_mode(&self) -> ColorMode {
+        self.color_mode
+    }
+
+    /// Use the actual colors of the terminal's palette to approximate RGB colors
+    pub fn set_terminal_palette(&mut self, palette: Palette) {
+        self.terminal_palette = Some(palette);
+    }
+
+    /// The style of a category, as used in `LS_COLORS`
+    pub fn get_style(&self, category: CategoryRef) -> Result<String> {
+        let style = self.resolve_style(category)?;
+        Ok(style.to_sgr(
+            &style.font_style.to_string(),
+            self.color_mode,
+            self.terminal_palette.as_ref(),
+        ))
+    }
+
+    /// The SGR parameters of a single color, approximated with the terminal's
+    /// palette like in [`Theme::get_style`]
+    pub fn get_color_style(&self, color: Color, color_type: ColorType) -> String {
+        color.get_style(color_type, self.color_mode, self.terminal_palette.as_ref())
+    }
+
+    /// The style of a category for output that goes directly to the terminal,
+    /// which can make use of extended underline styles
+    pub fn get_terminal_style(&self, category: CategoryRef) -> Result<String> {
+        let style = self.resolve_style(category)?;
+        Ok(style.to_sgr(
+            &style.font_style.extended_sgr(),
+            self.color_mode,
+            self.terminal_palette.as_ref(),
+        ))
+    }
+}
+
+/// The resolved style of a theme node
+#[derive(Debug, Clone, PartialEq)]
+pub struct Style {
+    /// The path of the theme node the style was read from, which can be a
+    /// parent of the category
+    pub node: Category,
+    pub font_style: FontStyle,
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub underline_color: Option<Color>,
+}
+
+impl Style {
+    pub fn to_sgr(
+        &self,
+        font_style: &str,
+        color_mode: ColorMode,
+        palette: Option<&Palette>,
+    ) -> String {
+        let mut style = font_style.to_string();
+        let colors = [
+            (self.foreground, ColorType::Foreground),
+            (self.background, ColorType::Background),
+            (self.underline_color, ColorType::Underline),
+        ];
+        for (color, color_type) in colors {
+            if let Some(code) = color.map(|c| c.get_style(color_type, color_mode, palette)) {
+                style.push_str(&format!(";{code}", code = code));
+            }
+        }
+        style
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Theme;
+    use super::{Theme, ThemeMeta, Variant};
     use crate::color::ColorMode;
 
     #[test]
@@ -164,5 +341,87 @@ mod tests {
 
         let style3 = theme.get_style(&["t3".into()]).unwrap();
         assert_eq!("1", style3);
+
+        assert_eq!(ThemeMeta::default(), theme.meta);
+        assert!(!theme.uses_ansi_colors());
+    }
+
+    #[test]
+    fn extended_font_styles() {
+        let theme = Theme::from_string(
+            "
+                colors:
+                  red: 'ff0000'
+
+                unimportant:
+                  font-style: [faint, strikethrough]
+
+                broken:
+                  foreground: red
+                  font-style: [reverse, curly-underline]
+            ",
+            ColorMode::BitDepth24,
+        )
+        .unwrap();
+
+        assert_eq!("2;9", theme.get_style(&["unimportant".into()]).unwrap());
+        assert_eq!(
+            "7;4;38;2;255;0;0",
+            theme.get_style(&["broken".into()]).unwrap()
+        );
+        assert_eq!(
+            "7;4:3;38;2;255;0;0",
+            theme.get_terminal_style(&["broken".into()]).unwrap()
+        );
+        assert!(!theme.uses_ansi_colors());
+    }
+
+    #[test]
+    fn underline_color() {
+        let theme = Theme::from_string(
+            "
+                colors:
+                  red: 'ff0000'
+                  ansi_red: 'ansi:red'
+
+                broken:
+                  font-style: underline
+                  underline-color: red
+
+                ansi:
+                  font-style: underline
+                  underline-color: ansi_red
+            ",
+            ColorMode::BitDepth8,
+        )
+        .unwrap();
+
+        assert_eq!("4;58;5;196", theme.get_style(&["broken".into()]).unwrap());
+        assert_eq!("4;58;5;1", theme.get_style(&["ansi".into()]).unwrap());
+    }
+
+    #[test]
+    fn meta() {
+        let theme = Theme::from_string(
+            "
+                meta:
+                  name: Example
+                  variant: light
+                  min-color-depth: ansi
+
+                colors:
+                  red: 'ansi:red'
+            ",
+            ColorMode::BitDepth24,
+        )
+        .unwrap();
+
+        assert_eq!(Some("Example".into()), theme.meta.name);
+        assert_eq!(Some(Variant::Light), theme.meta.variant);
+        assert_eq!(Some("ansi".into()), theme.meta.min_color_depth);
+        assert!(theme.uses_ansi_colors());
+
+        let invalid = "meta: {variant: dim}\ncolors: {}";
+        assert!(Theme::from_string(invalid, ColorMode::BitDepth24).is_err());
     }
 }
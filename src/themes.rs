@@ -0,0 +1,330 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use rust_embed::RustEmbed;
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+use crate::color::ColorMode;
+use crate::config::{ConfigDirs, Origin};
+use crate::document::DocumentFormat;
+use crate::error::{Result, VividError};
+use crate::theme::Theme;
+use crate::util::load_yaml_file;
+
+#[derive(RustEmbed)]
+#[folder = "themes/"]
+struct ThemeAssets;
+
+const THEME_PATH_SYSTEM: &str = "/usr/share/vivid/themes/";
+
+/// A place where a theme file was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThemeLocation {
+    pub origin: Origin,
+    pub path: Option<PathBuf>,
+}
+
+/// An available theme, and the themes of the same name that it shadows
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThemeEntry {
+    pub name: String,
+    pub location: ThemeLocation,
+    pub shadowed: Vec<ThemeLocation>,
+}
+
+/// All theme directories, in order of precedence
+fn theme_dirs(config_dirs: &ConfigDirs) -> Vec<(Origin, PathBuf)> {
+    let mut theme_dirs: Vec<(Origin, PathBuf)> = config_dirs
+        .iter()
+        .map(|(origin, dir)| (origin, dir.join("themes")))
+        .collect();
+    theme_dirs.push((Origin::System, PathBuf::from(THEME_PATH_SYSTEM)));
+    theme_dirs
+}
+
+pub fn available_themes(config_dirs: &ConfigDirs) -> Result<Vec<ThemeEntry>> {
+    let mut locations: Vec<(String, ThemeLocation)> = vec![];
+
+    for (origin, path) in theme_dirs(config_dirs) {
+        if !path.exists() {
+            continue;
+        }
+        let dir = fs::read_dir(&path).map_err(VividError::IoError)?;
+        let mut dir_locations = vec![];
+        for theme_file in dir {
+            let theme_file = theme_file.map_err(VividError::IoError)?;
+            let file_name = theme_file.file_name().into_string().map_err(|n| {
+                VividError::InvalidFileName(n.as_os_str().to_string_lossy().into_owned())
+            })?;
+            let (theme_name, precedence) = split_extension(&file_name);
+            dir_locations.push((
+                precedence,
+                theme_name.to_owned(),
+                ThemeLocation {
+                    origin,
+                    path: Some(theme_file.path()),
+                },
+            ));
+        }
+        // If a directory has the same theme in several formats, the first of
+        // `DocumentFormat::EXTENSIONS` wins
+        dir_locations.sort_by_key(|(precedence, _, _)| *precedence);
+        locations.extend(dir_locations.into_iter().map(|(_, n, l)| (n, l)));
+    }
+
+    // Embedded themes have the lowest precedence
+    locations.extend(ThemeAssets::iter().map(|theme_name| {
+        (
+            split_extension(&theme_name).0.to_owned(),
+            ThemeLocation {
+                origin: Origin::Embedded,
+                path: None,
+            },
+        )
+    }));
+
+    // The sort is stable, so the locations of each theme stay in order of precedence
+    locations.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut themes: Vec<ThemeEntry> = vec![];
+    for (name, location) in locations {
+        match themes.last_mut() {
+            Some(entry) if entry.name == name => entry.shadowed.push(location),
+            _ => themes.push(ThemeEntry {
+                name,
+                location,
+                shadowed: vec![],
+            }),
+        }
+    }
+    Ok(themes)
+}
+
+pub fn available_theme_names(config_dirs: &ConfigDirs) -> Result<Vec<String>> {
+    Ok(available_themes(config_dirs)?
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect())
+}
+
+/// Split a theme file name into the theme name and the precedence of its
+/// extension. Files without a known extension come last.
+fn split_extension(file_name: &str) -> (&str, usize) {
+    DocumentFormat::EXTENSIONS
+        .iter()
+        .enumerate()
+        .find_map(|(precedence, extension)| {
+            let name = file_name.strip_suffix(extension)?.strip_suffix('.')?;
+            Some((name, precedence))
+        })
+        .unwrap_or((file_name, DocumentFormat::EXTENSIONS.len()))
+}
+
+fn embedded_theme_contents(theme: &str) -> Option<String> {
+    let embedded_file = ThemeAssets::get(&format!("{}.yml", theme))?;
+    String::from_utf8(embedded_file.data.into_owned()).ok()
+}
+
+/// Find the file of a theme that is given by name or path. `None` if there
+/// is none, and only an embedded theme can have this name.
+fn find_theme_file(theme: &str, config_dirs: &ConfigDirs) -> Option<PathBuf> {
+    let mut theme_paths = vec![PathBuf::from(theme)];
+    for (_, dir) in theme_dirs(config_dirs) {
+        theme_paths.extend(
+            DocumentFormat::EXTENSIONS
+                .iter()
+                .map(|extension| dir.join(format!("{}.{}", theme, extension))),
+        );
+    }
+    theme_paths.into_iter().find(|p| p.is_file())
+}
+
+pub fn load_theme(theme: &str, config_dirs: &ConfigDirs, color_mode: ColorMode) -> Result<Theme> {
+    match find_theme_file(theme, config_dirs) {
+        Some(path) => Theme::from_path(&path, color_mode),
+        None => match embedded_theme_contents(theme) {
+            Some(contents) => Theme::from_string(&contents, color_mode),
+            None => Err(VividError::CouldNotFindTheme(theme.to_string())),
+        },
+    }
+}
+
+/// Load the source document of a theme, without interpreting it
+pub fn load_theme_document(theme: &str, config_dirs: &ConfigDirs) -> Result<Yaml> {
+    match find_theme_file(theme, config_dirs) {
+        Some(path) => {
+            let contents = load_yaml_file(&path)
+                .map_err(|_| VividError::CouldNotLoadTheme(path.to_string_lossy().into()))?;
+            DocumentFormat::from_path(&path).parse(&contents)
+        }
+        None => match embedded_theme_contents(theme) {
+            Some(contents) => DocumentFormat::Yaml.parse(&contents),
+            None => Err(VividError::CouldNotFindTheme(theme.to_string())),
+        },
+    }
+}
+
+/// Load the theme that a listing entry refers to
+pub fn load_entry(entry: &ThemeEntry, color_mode: ColorMode) -> Result<Theme> {
+    match &entry.location.path {
+        Some(path) => Theme::from_path(path, color_mode),
+        None => match embedded_theme_contents(&entry.name) {
+            Some(contents) => Theme::from_string(&contents, color_mode),
+            None => Err(VividError::CouldNotFindTheme(entry.name.clone())),
+        },
+    }
+}
+
+fn describe_location(location: &ThemeLocation) -> String {
+    match &location.path {
+        Some(path) => format!("{} ({})", location.origin.name(), path.display()),
+        None => location.origin.name().to_string(),
+    }
+}
+
+pub fn write_long(out: &mut dyn Write, themes: &[(ThemeEntry, Theme)]) -> io::Result<()> {
+    let name_width = themes.iter().map(|(e, _)| e.name.len()).max().unwrap_or(0);
+    for (entry, theme) in themes {
+        let mut line = format!(
+            "{:name_width$}  {:7}  {:4}  {}",
+            entry.name,
+            theme.meta.variant.map_or("-", |v| v.name()),
+            if theme.uses_ansi_colors() {
+                "ansi"
+            } else {
+                "rgb"
+            },
+            describe_location(&entry.location),
+            name_width = name_width
+        );
+        for shadowed in &entry.shadowed {
+            line.push_str(&format!(", shadows {}", describe_location(shadowed)));
+        }
+        if let Some(name) = &theme.meta.name {
+            line.push_str(&format!("  \"{}\"", name));
+        }
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+pub fn write_json(out: &mut dyn Write, themes: &[(ThemeEntry, Theme)]) -> Result<()> {
+    let string = |s: &str| Yaml::String(s.to_string());
+    let optional = |s: Option<&str>| s.map_or(Yaml::Null, string);
+    let location = |l: &ThemeLocation| {
+        let mut location = Hash::new();
+        location.insert(string("origin"), string(l.origin.name()));
+        location.insert(
+            string("path"),
+            optional(l.path.as_ref().map(|p| p.display().to_string()).as_deref()),
+        );
+        Yaml::Hash(location)
+    };
+
+    let mut list = Vec::new();
+    for (entry, theme) in themes {
+        let meta = &theme.meta;
+        let mut item = Hash::new();
+        item.insert(string("name"), string(&entry.name));
+        item.insert(string("display-name"), optional(meta.name.as_deref()));
+        item.insert(string("author"), optional(meta.author.as_deref()));
+        item.insert(string("license"), optional(meta.license.as_deref()));
+        item.insert(string("variant"), optional(meta.variant.map(|v| v.name())));
+        item.insert(string("url"), optional(meta.url.as_deref()));
+        item.insert(
+            string("min-color-depth"),
+            optional(meta.min_color_depth.as_deref()),
+        );
+        item.insert(
+            string("uses-ansi-colors"),
+            Yaml::Boolean(theme.uses_ansi_colors()),
+        );
+        item.insert(string("location"), location(&entry.location));
+        item.insert(
+            string("shadows"),
+            Yaml::Array(entry.shadowed.iter().map(location).collect()),
+        );
+        list.push(Yaml::Hash(item));
+    }
+
+    let json = DocumentFormat::Json.to_string(&Yaml::Array(list))?;
+    write!(out, "{}", json).map_err(VividError::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{available_themes, load_theme};
+    use crate::color::ColorMode;
+    use crate::config::{ConfigDirs, Origin};
+
+    #[test]
+    fn user_themes_shadow_embedded_ones() {
+        let dir = std::env::temp_dir().join(format!("vivid-themes-{}", std::process::id()));
+        fs::create_dir_all(dir.join("themes")).unwrap();
+        fs::write(dir.join("themes/molokai.yml"), "colors: {}").unwrap();
+        fs::write(dir.join("themes/my-theme.yml"), "colors: {}").unwrap();
+
+        let config_dirs = ConfigDirs {
+            overlays: vec![],
+            user: dir.clone(),
+        };
+        let themes = available_themes(&config_dirs).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let molokai = themes.iter().find(|t| t.name == "molokai").unwrap();
+        assert_eq!(Origin::User, molokai.location.origin);
+        assert_eq!(Some(dir.join("themes/molokai.yml")), molokai.location.path);
+        assert_eq!(
+            vec![Origin::Embedded],
+            molokai
+                .shadowed
+                .iter()
+                .map(|l| l.origin)
+                .collect::<Vec<_>>()
+        );
+
+        let my_theme = themes.iter().find(|t| t.name == "my-theme").unwrap();
+        assert!(my_theme.shadowed.is_empty());
+
+        let snazzy = themes.iter().find(|t| t.name == "snazzy").unwrap();
+        assert_eq!(Origin::Embedded, snazzy.location.origin);
+    }
+
+    #[test]
+    fn toml_and_json_themes() {
+        let dir = std::env::temp_dir().join(format!("vivid-themes-formats-{}", std::process::id()));
+        fs::create_dir_all(dir.join("themes")).unwrap();
+        fs::write(
+            dir.join("themes/toml-theme.toml"),
+            "[colors]\nred = 'ff0000'\n\n[core.directory]\nforeground = 'red'\n",
+        )
+        .unwrap();
+        fs::write(dir.join("themes/both.yml"), "colors: {}").unwrap();
+        fs::write(dir.join("themes/both.json"), "{\"colors\": {}}").unwrap();
+
+        let config_dirs = ConfigDirs {
+            overlays: vec![],
+            user: dir.clone(),
+        };
+        let themes = available_themes(&config_dirs).unwrap();
+        let theme = load_theme("toml-theme", &config_dirs, ColorMode::BitDepth24);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(themes.iter().any(|t| t.name == "toml-theme"));
+        let both = themes.iter().find(|t| t.name == "both").unwrap();
+        assert_eq!(Some(dir.join("themes/both.yml")), both.location.path);
+        assert_eq!(Some(dir.join("themes/both.json")), both.shadowed[0].path);
+
+        assert_eq!(
+            "0;38;2;255;0;0",
+            theme
+                .unwrap()
+                .get_style(&["core".into(), "directory".into()])
+                .unwrap()
+        );
+    }
+}
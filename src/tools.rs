@@ -0,0 +1,219 @@
+use crate::error::{Result, VividError};
+use crate::theme::Theme;
+
+/// The styles of the optional `tools` section of a theme, with the theme node
+/// that provides the style if the theme does not
+pub const SLOTS: [(&str, &str); 11] = [
+    ("match", "core.broken_symlink"),
+    ("filename", "core.directory"),
+    ("line-number", "unimportant"),
+    ("error", "core.broken_symlink"),
+    ("warning", "text.todo"),
+    ("note", "core.symlink"),
+    ("man-bold", "core.directory"),
+    ("man-underline", "core.symlink"),
+    ("json-key", "core.directory"),
+    ("json-string", "programming.source"),
+    ("json-number", "core.executable_file"),
+];
+
+/// The fields of `JQ_COLORS`, in order, with the slot that styles them or
+/// jq's default style
+const JQ_FIELDS: [(Option<&str>, &str); 8] = [
+    (None, "1;30"), // null
+    (None, "0;39"), // false
+    (None, "0;39"), // true
+    (Some("json-number"), "0;39"),
+    (Some("json-string"), "0;32"),
+    (None, "1;39"), // arrays
+    (None, "1;39"), // objects
+    (Some("json-key"), "34;1"),
+];
+
+const RESET: &str = "\x1b[0m";
+
+/// The SGR parameters of a theme node, or `None` if the theme does not style it
+fn style(theme: &Theme, category: &[String]) -> Result<Option<String>> {
+    match theme.get_style(category) {
+        Ok(sgr) => Ok(Some(sgr)),
+        Err(VividError::CouldNotFindStyleFor(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The environment variables that make grep, GCC, less (for man pages) and jq
+/// use the styles of the theme's `tools` section. Slots that the section does
+/// not style get the style of a file type of the theme, like `core.directory`
+/// for `filename`. Variables for which the theme has no styles are left out.
+pub fn variables(theme: &Theme) -> Result<Vec<(&'static str, String)>> {
+    let mut styles = Vec::new();
+    for (slot, default) in SLOTS {
+        let sgr = match style(theme, &["tools".into(), slot.into()])? {
+            Some(sgr) => Some(sgr),
+            None => style(
+                theme,
+                &default.split('.').map(String::from).collect::<Vec<_>>(),
+            )?,
+        };
+        if let Some(sgr) = sgr {
+            styles.push((slot, sgr));
+        }
+    }
+    if styles.is_empty() {
+        return Err(VividError::NoToolStyles);
+    }
+    let get = |slot: &str| {
+        styles
+            .iter()
+            .find(|(s, _)| *s == slot)
+            .map(|(_, sgr)| sgr.as_str())
+    };
+
+    // `key=sgr` pairs of the given slots, separated by colons
+    let pairs = |keys: &[(&str, &str)]| {
+        keys.iter()
+            .filter_map(|(key, slot)| Some(format!("{}={}", key, get(slot)?)))
+            .collect::<Vec<_>>()
+            .join(":")
+    };
+
+    let mut variables = vec![
+        (
+            "GREP_COLORS",
+            pairs(&[
+                ("ms", "match"),
+                ("mc", "match"),
+                ("fn", "filename"),
+                ("ln", "line-number"),
+                ("bn", "line-number"),
+            ]),
+        ),
+        (
+            "GCC_COLORS",
+            pairs(&[
+                ("error", "error"),
+                ("warning", "warning"),
+                ("note", "note"),
+                ("caret", "match"),
+                ("locus", "filename"),
+            ]),
+        ),
+    ];
+    for (start, end, slot) in [
+        ("LESS_TERMCAP_md", "LESS_TERMCAP_me", "man-bold"),
+        ("LESS_TERMCAP_us", "LESS_TERMCAP_ue", "man-underline"),
+    ] {
+        if let Some(sgr) = get(slot) {
+            variables.push((start, format!("\x1b[{}m", sgr)));
+            variables.push((end, RESET.to_string()));
+        }
+    }
+    if JQ_FIELDS
+        .iter()
+        .any(|(slot, _)| slot.and_then(get).is_some())
+    {
+        let fields = JQ_FIELDS
+            .iter()
+            .map(|(slot, default)| slot.and_then(get).unwrap_or(default))
+            .collect::<Vec<_>>();
+        variables.push(("JQ_COLORS", fields.join(":")));
+    }
+
+    variables.retain(|(_, value)| !value.is_empty());
+    Ok(variables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::variables;
+    use crate::color::ColorMode;
+    use crate::error::VividError;
+    use crate::theme::Theme;
+
+    #[test]
+    fn tool_variables() {
+        let theme = Theme::from_string(
+            "
+colors:
+  red: 'ff0000'
+  purple: 'ansi:magenta'
+core:
+  directory:
+    foreground: purple
+tools:
+  match:
+    foreground: red
+    font-style: bold
+  filename:
+    foreground: purple
+  man-bold:
+    font-style: bold
+  json-key:
+    foreground: purple
+",
+            ColorMode::BitDepth8,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                ("GREP_COLORS", "ms=1;38;5;196:mc=1;38;5;196:fn=0;35".into()),
+                ("GCC_COLORS", "caret=1;38;5;196:locus=0;35".into()),
+                ("LESS_TERMCAP_md", "\x1b[1m".into()),
+                ("LESS_TERMCAP_me", "\x1b[0m".into()),
+                (
+                    "JQ_COLORS",
+                    "1;30:0;39:0;39:0;39:0;32:1;39:1;39:0;35".into()
+                ),
+            ],
+            variables(&theme).unwrap()
+        );
+
+        let theme = Theme::from_string("colors: {}\ncore: {}", ColorMode::BitDepth8).unwrap();
+        assert!(matches!(variables(&theme), Err(VividError::NoToolStyles)));
+
+        let theme = Theme::from_string(
+            "colors: {}\ntools:\n  error:\n    foreground: nope",
+            ColorMode::BitDepth8,
+        )
+        .unwrap();
+        assert!(matches!(
+            variables(&theme),
+            Err(VividError::UnknownColor(_))
+        ));
+    }
+
+    #[test]
+    fn default_styles() {
+        let theme = Theme::from_string(
+            "
+colors:
+  red: 'ansi:red'
+  blue: 'ansi:blue'
+core:
+  directory:
+    foreground: blue
+    font-style: bold
+  broken_symlink:
+    foreground: red
+tools:
+  filename:
+    foreground: red
+",
+            ColorMode::BitDepth8,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                ("GREP_COLORS", "ms=0;31:mc=0;31:fn=0;31".into()),
+                ("GCC_COLORS", "error=0;31:caret=0;31:locus=0;31".into()),
+                ("LESS_TERMCAP_md", "\x1b[1;34m".into()),
+                ("LESS_TERMCAP_me", "\x1b[0m".into()),
+                (
+                    "JQ_COLORS",
+                    "1;30:0;39:0;39:0;39:0;32:1;39:1;39:1;34".into()
+                ),
+            ],
+            variables(&theme).unwrap()
+        );
+    }
+}
@@ -23,6 +23,21 @@ pub fn get_first_existing_path<'a>(paths: &[&'a Path]) -> Option<&'a Path> {
     paths.iter().find(|p| Path::exists(p)).copied()
 }
 
-pub fn get_all_existing_paths<'a>(paths: &[&'a Path]) -> Vec<&'a Path> {
-    paths.iter().cloned().filter(|p| Path::exists(p)).collect()
+/// Quote a string for use in JSON output
+pub fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
 }
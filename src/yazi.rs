@@ -0,0 +1,295 @@
+use std::io::Write;
+
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+use crate::color::{Ansi3Bit, Color};
+use crate::document::DocumentFormat;
+use crate::error::{Result, VividError};
+use crate::filetypes::FileTypes;
+use crate::theme::{Style, Theme};
+use crate::types::{Category, FileType};
+
+/// Rules for the core file kinds, in the order in which yazi has to try them:
+/// the file name pattern, the `is` condition and the vivid core category
+const CORE_RULES: [(&str, Option<&str>, &str); 9] = [
+    ("*", Some("orphan"), "broken_symlink"),
+    ("*", Some("link"), "symlink"),
+    ("*", Some("block"), "block_device"),
+    ("*", Some("char"), "character_device"),
+    ("*", Some("fifo"), "fifo"),
+    ("*", Some("sock"), "socket"),
+    ("*/", Some("sticky"), "sticky"),
+    ("*/", None, "directory"),
+    ("*", Some("exec"), "executable_file"),
+];
+
+/// Categories whose files yazi can recognize by their MIME type instead of a
+/// list of names. Vector images and fonts also contain formats with other
+/// MIME types (`.eps`, `.afm`), so they keep their name rules.
+const MIME_RULES: [(&str, &[&str]); 3] = [
+    ("image/*", &["media.image.bitmap", "media.image.raw"]),
+    ("audio/*", &["media.audio"]),
+    ("video/*", &["media.video"]),
+];
+
+/// The yazi style properties of vivid's font styles
+const PROPERTIES: [(&str, &str); 13] = [
+    ("bold", "bold"),
+    ("faint", "dim"),
+    ("italic", "italic"),
+    ("underline", "underline"),
+    ("double-underline", "underline"),
+    ("curly-underline", "underline"),
+    ("dotted-underline", "underline"),
+    ("dashed-underline", "underline"),
+    ("blink", "blink"),
+    ("rapid-blink", "blink_rapid"),
+    ("reverse", "reversed"),
+    ("hidden", "hidden"),
+    ("strikethrough", "crossed"),
+];
+
+/// A color as yazi expects it: a hex value or the name of an ANSI color
+fn color_value(color: Color) -> String {
+    let name = match color {
+        Color::Rgb(..) => return color.to_string(),
+        Color::Ansi3Bit(color) => match color {
+            Ansi3Bit::Black => "black",
+            Ansi3Bit::Red => "red",
+            Ansi3Bit::Green => "green",
+            Ansi3Bit::Yellow => "yellow",
+            Ansi3Bit::Blue => "blue",
+            Ansi3Bit::Magenta => "magenta",
+            Ansi3Bit::Cyan => "cyan",
+            Ansi3Bit::White => "gray",
+            Ansi3Bit::BrightBlack => "darkgray",
+            Ansi3Bit::BrightRed => "lightred",
+            Ansi3Bit::BrightGreen => "lightgreen",
+            Ansi3Bit::BrightYellow => "lightyellow",
+            Ansi3Bit::BrightBlue => "lightblue",
+            Ansi3Bit::BrightMagenta => "lightmagenta",
+            Ansi3Bit::BrightCyan => "lightcyan",
+            Ansi3Bit::BrightWhite => "white",
+        },
+    };
+    name.into()
+}
+
+fn string(s: &str) -> Yaml {
+    Yaml::String(s.to_string())
+}
+
+/// A rule that matches files by `key` (`name` or `mime`), with the style
+/// properties of `style`. Returns `None` if the style is empty. Underline
+/// colors have no equivalent in yazi.
+fn rule(key: &str, pattern: &str, is: Option<&str>, style: &Style) -> Option<Yaml> {
+    let mut properties = Hash::new();
+    if let Some(color) = style.foreground {
+        properties.insert(string("fg"), Yaml::String(color_value(color)));
+    }
+    if let Some(color) = style.background {
+        properties.insert(string("bg"), Yaml::String(color_value(color)));
+    }
+    for name in style.font_style.names() {
+        if let Some((_, property)) = PROPERTIES.iter().find(|(n, _)| *n == name) {
+            properties.insert(string(property), Yaml::Boolean(true));
+        }
+    }
+    if properties.is_empty() {
+        return None;
+    }
+
+    let mut rule = Hash::new();
+    rule.insert(string(key), string(pattern));
+    if let Some(is) = is {
+        rule.insert(string("is"), string(is));
+    }
+    rule.extend(properties);
+    Some(Yaml::Hash(rule))
+}
+
+/// Whether a database entry belongs to a category given as `a.b.c`
+fn is_in(category: &Category, path: &str) -> bool {
+    let path = path.split('.').collect::<Vec<_>>();
+    category.len() >= path.len() && category.iter().zip(&path).all(|(a, b)| a == b)
+}
+
+/// Write the `[filetype]` section of a yazi theme. Yazi uses the first rule
+/// that matches, so the core kinds come first, then the name patterns from the
+/// longest to the shortest, and the MIME types last.
+pub fn write(
+    out: &mut dyn Write,
+    theme_name: &str,
+    filetypes: &FileTypes,
+    theme: &Theme,
+) -> Result<()> {
+    let mut rules = Vec::new();
+    for (name, is, key) in CORE_RULES {
+        let style = theme.resolve_style(&["core".into(), key.into()])?;
+        rules.extend(rule("name", name, is, &style));
+    }
+
+    let mut patterns = filetypes
+        .mapping
+        .iter()
+        .filter(|(key, _)| key.starts_with('*'))
+        .chain(filetypes.globs.iter())
+        .map(|(key, category)| Ok((key, category, theme.resolve_style(category)?)))
+        .collect::<Result<Vec<(&FileType, &Category, Style)>>>()?;
+
+    let mut mime_rules = Vec::new();
+    for (mime, categories) in MIME_RULES {
+        let is_covered = |category: &Category| categories.iter().any(|c| is_in(category, c));
+        let mut styles = patterns
+            .iter()
+            .filter(|(_, category, _)| is_covered(category))
+            .map(|(_, _, style)| style);
+        let style = match styles.next() {
+            Some(style) => style.clone(),
+            None => continue,
+        };
+        // Only replace the names if the theme does not tell the files apart
+        if styles.all(|s| *s == style) {
+            patterns.retain(|(_, category, _)| !is_covered(category));
+            mime_rules.extend(rule("mime", mime, None, &style));
+        }
+    }
+
+    patterns.sort_unstable_by(|(a, _, _), (b, _, _)| (b.len(), a).cmp(&(a.len(), b)));
+    for (key, _, style) in &patterns {
+        rules.extend(rule("name", key, None, style));
+    }
+    rules.extend(mime_rules);
+
+    let mut filetype = Hash::new();
+    filetype.insert(string("rules"), Yaml::Array(rules));
+    let mut doc = Hash::new();
+    doc.insert(string("filetype"), Yaml::Hash(filetype));
+
+    write!(
+        out,
+        "# yazi theme generated by vivid from the theme '{}'\n\n{}",
+        theme_name,
+        DocumentFormat::Toml.to_string(&Yaml::Hash(doc))?
+    )
+    .map_err(VividError::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use yaml_rust::Yaml;
+
+    use super::write;
+    use crate::color::ColorMode;
+    use crate::document::DocumentFormat;
+    use crate::filetypes::FileTypes;
+    use crate::theme::Theme;
+
+    const DATABASE: &str = "
+        core:
+          directory: [$di]
+          symlink: [$ln]
+          broken_symlink: [$or]
+          block_device: [$bd]
+          character_device: [$cd]
+          fifo: [$pi]
+          socket: [$so]
+          sticky: [$st]
+          executable_file: [$ex]
+        text:
+          readme: [README.md]
+          markdown: [.md]
+        media:
+          image:
+            bitmap: [.png, .jpg]
+            raw: [.cr2]
+          audio:
+            lossy: [.mp3]
+            lossless: [.flac]
+        archives: ['*.tar.*']
+    ";
+
+    fn output(theme: &str) -> String {
+        let filetypes = FileTypes::from_string(DATABASE).unwrap();
+        let theme = Theme::from_string(theme, ColorMode::BitDepth24).unwrap();
+        let mut out = Vec::new();
+        write(&mut out, "example", &filetypes, &theme).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// The rules of the output, one line per rule
+    fn rules_of(output: &str) -> Vec<String> {
+        let doc = DocumentFormat::Toml.parse(output).unwrap();
+        let rules = doc["filetype"]["rules"].as_vec().unwrap();
+        rules
+            .iter()
+            .map(|rule| {
+                let rule = rule.as_hash().unwrap().iter();
+                rule.map(|(key, value)| match value {
+                    Yaml::String(s) => format!("{} = {}", key.as_str().unwrap(), s),
+                    Yaml::Boolean(b) => format!("{} = {}", key.as_str().unwrap(), b),
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rules() {
+        let out = output(
+            "
+                colors:
+                  blue: '0000ff'
+                core:
+                  directory:
+                    foreground: blue
+                    font-style: bold
+                  symlink:
+                    foreground: 'ansi:cyan'
+                  broken_symlink:
+                    foreground: 'ansi:red'
+                    font-style: reverse
+                  block_device: {}
+                  character_device: {}
+                  fifo: {}
+                  socket: {}
+                  sticky: {}
+                  executable_file:
+                    foreground: 'ansi:bright_green'
+                    font-style: bold
+                text:
+                  readme:
+                    font-style: [bold, underline, curly-underline]
+                  markdown: {}
+                media:
+                  image:
+                    foreground: 'ansi:magenta'
+                    font-style: italic
+                  audio:
+                    foreground: '00ff00'
+                    lossy:
+                      foreground: '00ffff'
+                archives:
+                  foreground: 'ansi:red'
+            ",
+        );
+        assert!(out.starts_with("# yazi theme generated by vivid from the theme 'example'\n\n"));
+        assert_eq!(
+            vec![
+                "name = *, is = orphan, fg = red, reversed = true",
+                "name = *, is = link, fg = cyan",
+                "name = */, fg = #0000ff, bold = true",
+                "name = *, is = exec, fg = lightgreen, bold = true",
+                "name = *README.md, bold = true, underline = true",
+                "name = *.tar.*, fg = red",
+                "name = *.flac, fg = #00ff00",
+                "name = *.mp3, fg = #00ffff",
+                "mime = image/*, fg = magenta, italic = true",
+            ],
+            rules_of(&out)
+        );
+    }
+}
@@ -17,3 +17,42 @@ fn can_call_vivid_generate_for_all_themes() {
         cmd.arg("generate").arg(theme_name).assert().success();
     }
 }
+
+#[test]
+fn config_file_settings_and_precedence() {
+    let config_path = std::env::temp_dir().join(format!("vivid-test-{}.yml", std::process::id()));
+    std::fs::write(
+        &config_path,
+        "theme: molokai\nformat: sh\nterms:\n  linux:\n    color-mode: 8-bit\n",
+    )
+    .unwrap();
+
+    let output = Command::cargo_bin("vivid")
+        .unwrap()
+        .env("VIVID_CONFIG", &config_path)
+        .env("TERM", "linux")
+        .env_remove("VIVID_THEME")
+        .env_remove("VIVID_COLOR_MODE")
+        .arg("generate")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("export LS_COLORS='"));
+    assert!(stdout.contains(";5;"));
+
+    let output = Command::cargo_bin("vivid")
+        .unwrap()
+        .env("VIVID_CONFIG", &config_path)
+        .env("TERM", "linux")
+        .env("VIVID_THEME", "snazzy")
+        .args(["--color-mode", "24-bit", "config", "show"])
+        .output()
+        .unwrap();
+    std::fs::remove_file(&config_path).unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("(environment variable VIVID_THEME)"));
+    assert!(stdout.contains("24-bit"));
+    assert!(stdout.contains("(command line)"));
+    assert!(stdout.contains(&format!("(config file {})", config_path.display())));
+}